@@ -6,5 +6,6 @@ cargo build --target wasm32-unknown-unknown --release
 cp target/wasm32-unknown-unknown/release/obby.wasm dist/obby.wasm
 cp index.html dist/index.html
 cp mq_js_bundle.js dist/mq_js_bundle.js
+cp storage.js dist/storage.js
 cp favicon.png dist/favicon.png
 cp -r res dist/res
\ No newline at end of file
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use crate::{AssetError, Map, MapTile};
+
+/// Characters of the grid, the kinds they set and the tile of the default tileset they are drawn with.
+const LEGEND: [(char, &str, u32); 6] = [
+    ('#', "block", 20),
+    ('^', "block deadly", 41),
+    ('P', "entity player", 1),
+    ('G', "entity goal", 2),
+    ('c', "entity coin", 21),
+    ('~', "entity cloud", 81),
+];
+
+/// A map written as plain text, one character per cell, for tests and quick prototypes.
+///
+/// An optional header of `key = value` lines ends at a line of `---`, `background`
+/// is a `#rrggbb` color and `music` a track, other keys are kept as properties.
+/// Lines starting with `#` in the header are comments. The grid uses `#` for blocks,
+/// `^` for deadly blocks, `P`, `G`, `c` and `~` for the player, goal, coins and clouds,
+/// and `.` or space for nothing.
+///
+/// ```text
+/// background = #3d3846
+/// ---
+/// ..........
+/// .P..c...G.
+/// ####^^####
+/// ```
+#[derive(Default, Clone)]
+pub struct AsciiMap {
+    pub width: u32,
+    pub height: u32,
+    pub background: (u8, u8, u8),
+    pub properties: HashMap<String, String>,
+    cells: Vec<MapTile>,
+}
+
+impl AsciiMap {
+    pub fn parse(path: &str, text: &str) -> Result<Self, AssetError> {
+        let lines: Vec<&str> = text.lines().map(|l| l.trim_end()).collect();
+        let (header, grid) = match lines.iter().position(|l| *l == "---") {
+            Some(split) => (&lines[..split], &lines[split + 1..]),
+            None => (&lines[..0], &lines[..]),
+        };
+
+        let mut map = AsciiMap::default();
+        for (n, line) in header.iter().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(AssetError::new(path, format!("line {}: expected key = value", n + 1)));
+            };
+            map.properties.insert(key.trim().to_string(), value.trim().to_string());
+        }
+        if let Some(color) = map.properties.get("background") {
+            map.background = parse_color(color).ok_or_else(|| AssetError::new(path, format!("'{color}' is not a #rrggbb color")))?;
+        }
+
+        let first_line = lines.len() - grid.len() + 1;
+        map.width = grid.iter().map(|l| l.chars().count()).max().unwrap_or_default() as u32;
+        map.height = grid.len() as u32;
+        map.cells = vec![MapTile::default(); (map.width * map.height) as usize];
+        for (y, line) in grid.iter().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                if c == '.' || c == ' ' {
+                    continue;
+                }
+                let Some((_, kinds, variant)) = LEGEND.iter().find(|(legend, _, _)| *legend == c) else {
+                    return Err(AssetError::new(path, format!("line {}: unknown tile '{c}'", first_line + y)));
+                };
+                let cell = &mut map.cells[y * map.width as usize + x];
+                cell.variant = *variant;
+                for kind in kinds.split_whitespace() {
+                    cell.set_kind(kind);
+                }
+            }
+        }
+        Ok(map)
+    }
+
+    pub fn property(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).map(String::as_str)
+    }
+}
+
+/// Parses `#rrggbb`, anything before or after it is rejected.
+pub fn parse_color(s: &str) -> Option<(u8, u8, u8)> {
+    let hex = s.strip_prefix('#').filter(|hex| hex.len() == 6 && hex.bytes().all(|b| b.is_ascii_hexdigit()))?;
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
+
+impl Map for AsciiMap {
+    fn background(&self) -> (u8, u8, u8) {
+        self.background
+    }
+
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn tile(&self, x: i32, y: i32) -> MapTile {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return MapTile::default();
+        }
+        self.cells[y as usize * self.width as usize + x as usize].clone()
+    }
+
+    fn music(&self) -> Option<String> {
+        self.property("music").map(str::to_string)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = "# comment
+background = #102030
+music = res/music/level.ogg
+author = someone
+---
+......
+.P..c.  
+##^#
+~   G
+";
+
+    #[test]
+    fn parse_map() {
+        let map = AsciiMap::parse("test.map", MAP).unwrap();
+        assert_eq!((map.width(), map.height()), (6, 4));
+        assert_eq!(map.background(), (0x10, 0x20, 0x30));
+        assert_eq!(map.music().as_deref(), Some("res/music/level.ogg"));
+        assert_eq!(map.property("author"), Some("someone"));
+
+        let player = map.tile(1, 1);
+        assert!(player.is_entity && player.is_player && !player.is_block);
+        assert!(map.tile(4, 1).is_coin && map.tile(0, 3).is_cloud && map.tile(4, 3).is_goal);
+        let block = map.tile(0, 2);
+        assert!(block.is_block && !block.is_deadly);
+        assert_eq!(block.variant, 20);
+        let spikes = map.tile(2, 2);
+        assert!(spikes.is_block && spikes.is_deadly);
+        assert_eq!(spikes.variant, 41);
+        // short lines, spaces and tiles outside the map are empty
+        for (x, y) in [(5, 2), (1, 3), (0, 0), (-1, 1), (6, 1), (0, 4)] {
+            let tile = map.tile(x, y);
+            assert!(!tile.is_block && !tile.is_entity, "({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn parse_grid_without_header() {
+        let map = AsciiMap::parse("test.map", ".P\n##\n").unwrap();
+        assert_eq!((map.width(), map.height()), (2, 2));
+        assert_eq!(map.background(), (0, 0, 0));
+        assert!(map.tile(1, 0).is_player && map.tile(0, 1).is_block);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |text: &str| AsciiMap::parse("test.map", text).err().expect(text).to_string();
+        let err = error("background = #000\n---\n#\n");
+        assert!(err.contains("#rrggbb"), "{err}");
+        let err = error("nonsense\n---\n#\n");
+        assert!(err.contains("line 1"), "{err}");
+        let err = error("# header\n---\n..\n.X\n");
+        assert!(err.contains("line 4") && err.contains("'X'"), "{err}");
+    }
+
+    #[test]
+    fn parse_colors() {
+        assert_eq!(parse_color("#3d3846"), Some((0x3d, 0x38, 0x46)));
+        assert_eq!(parse_color("#FFffFF"), Some((255, 255, 255)));
+        for bad in ["3d3846", "#3d384", "#3d38466", "#3d3846ff", "#3d3846 ", "#+f+f+f", "#3d38g6", "#ø3846"] {
+            assert_eq!(parse_color(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn sample_map() {
+        let map = AsciiMap::parse("res/maps/sample.map", include_str!("../../res/maps/sample.map")).unwrap();
+        assert_eq!((map.width(), map.height()), (20, 6));
+        let entities = crate::entity_tiles(&map);
+        assert_eq!(entities.iter().filter(|(_, tile)| tile.is_player).count(), 1);
+        assert_eq!(entities.iter().filter(|(_, tile)| tile.is_goal).count(), 1);
+        assert!(map.tile(8, 5).is_deadly);
+    }
+}
@@ -1,6 +1,6 @@
-use std::rc::Rc;
+use std::{collections::HashSet, rc::Rc};
 use glam::Vec2;
-use crate::Map;
+use crate::{AssetError, Ghost, Map};
 
 pub trait Context {
     fn map(&mut self, name: &str) -> MapResult;
@@ -9,19 +9,47 @@ pub trait Context {
     fn is_key_down(&self, key: Keys) -> bool;
     fn is_key_pressed(&self, key:Keys) -> bool;
     fn is_any_key_pressed(&self) -> bool;
+    fn input(&self, player: u32) -> &Input;
     fn map_list(&self) -> &Vec<String>;
     fn rand_f32(&self) -> f32;
+    fn ghost(&mut self, map_name: &str) -> Option<Rc<Ghost>>;
 }
 
 pub enum MapResult {
-    NotFound,
+    Error(AssetError),
     Pending,
     Ok(Rc<dyn Map>),
 }
 
-#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
 pub enum Keys {
     Space,
     Left,
-    Right
+    Right,
+    Up,
+    Down,
+    Confirm,
+    Back,
+    Pause
+}
+
+/// Input state of a single local player.
+///
+/// `d_pad` has a length of at most one, analog sticks give smaller values when partially tilted.
+#[derive(Default, Clone)]
+pub struct Input {
+    pub d_pad: Vec2,
+    pub stick: Vec2,
+    pub keys_down: HashSet<Keys>,
+    pub keys_pressed: HashSet<Keys>,
+}
+
+impl Input {
+    pub fn is_key_down(&self, key: Keys) -> bool {
+        self.keys_down.contains(&key)
+    }
+
+    pub fn is_key_pressed(&self, key: Keys) -> bool {
+        self.keys_pressed.contains(&key)
+    }
 }
@@ -16,10 +16,20 @@ pub struct Entity {
     pub timer0: Timer,
     pub dir_x: DirX,
     pub variant:EntityVariant,
-    pub clip:Clip
+    pub clip:Clip,
+    pub anim:AnimState,
+    /// seconds since `anim` last changed
+    pub anim_elapsed_sec:f32
 }
 
 impl Entity {
+    /// Returns the local player index if this is a player.
+    pub fn player_index(&self) -> Option<u32> {
+        match self.variant {
+            EntityVariant::Player { index, .. } => Some(index),
+            _ => None,
+        }
+    }
     pub fn cell(&self) -> (i32, i32) {
         let cell = self.pos.as_ivec2();
         cell.into()
@@ -27,6 +37,13 @@ impl Entity {
     pub fn body(&self) -> Body<'_> {
         Body::Entity(self)
     }
+    /// Changes the animation state, restarting the animation if it differs.
+    pub fn set_anim(&mut self, anim: AnimState) {
+        if self.anim != anim {
+            self.anim = anim;
+            self.anim_elapsed_sec = 0.0;
+        }
+    }
 }
 
 impl Default for Entity {
@@ -44,12 +61,14 @@ impl Default for Entity {
             dir_x: Default::default(),
             variant:EntityVariant::Unknown,
             pos_start: Default::default(),
-            clip:Clip::Clip
+            clip:Clip::Clip,
+            anim:AnimState::Idle,
+            anim_elapsed_sec:0.0
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 #[derive(Default)]
 pub enum DirX {
     Left,
@@ -62,11 +81,53 @@ pub enum DirX {
 pub enum EntityVariant {
     Unknown,
     Player {
-        skin:u32
+        skin:u32,
+        index:u32
     },
     Goal,
     Coin,
-    Cloud
+    Cloud,
+    Ghost {
+        skin:u32
+    }
+}
+
+/// What an entity is doing, the frontend picks an animation from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum AnimState {
+    #[default]
+    Idle,
+    Run,
+    Jump,
+    Fall,
+    Dead,
+    Won,
+}
+
+impl AnimState {
+    pub const ALL: [AnimState; 6] = [AnimState::Idle, AnimState::Run, AnimState::Jump, AnimState::Fall, AnimState::Dead, AnimState::Won];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AnimState::Idle => "idle",
+            AnimState::Run => "run",
+            AnimState::Jump => "jump",
+            AnimState::Fall => "fall",
+            AnimState::Dead => "dead",
+            AnimState::Won => "won",
+        }
+    }
+
+    /// Picks idle, run, jump or fall from how a body is moving.
+    pub fn from_motion(vel: Vec2, on_floor: bool) -> Self {
+        if !on_floor {
+            if vel.y < 0.0 { AnimState::Jump } else { AnimState::Fall }
+        } else if vel.x.abs() > 0.1 {
+            AnimState::Run
+        } else {
+            AnimState::Idle
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -0,0 +1,22 @@
+use std::fmt;
+
+/// An asset that could not be loaded, with the path and what went wrong.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssetError {
+    pub path: String,
+    pub cause: String,
+}
+
+impl AssetError {
+    pub fn new(path: impl Into<String>, cause: impl ToString) -> Self {
+        Self { path: path.into(), cause: cause.to_string() }
+    }
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.cause)
+    }
+}
+
+impl std::error::Error for AssetError {}
@@ -1,11 +1,48 @@
+use glam::Vec2;
 
-pub enum Event {
+use crate::{AssetError, Entity};
+
+pub enum EventKind {
     PickupCoin,
     Won,
     Died,
     PickupExtraLife,
     PlayerJump,
+    LevelStarted,
+    CloudCrumble,
     GameOver {
         score:u32
+    },
+    /// An asset the game needs failed to load, the game cannot go on.
+    Error(AssetError)
+}
+
+/// Something that happened during an update, for the frontend to play sounds and such.
+///
+/// Events raised by an entity carry its world position and id, global events such as
+/// `GameOver` have neither.
+pub struct Event {
+    pub kind: EventKind,
+    pub pos: Option<Vec2>,
+    pub source: Option<u32>,
+}
+
+impl Event {
+    pub fn at(kind: EventKind, e: &Entity) -> Self {
+        Self {
+            kind,
+            pos: Some(e.pos),
+            source: Some(e.id),
+        }
+    }
+}
+
+impl From<EventKind> for Event {
+    fn from(kind: EventKind) -> Self {
+        Self {
+            kind,
+            pos: None,
+            source: None,
+        }
     }
-}
\ No newline at end of file
+}
@@ -1,7 +1,30 @@
-use std::{collections::HashMap, rc::Rc};
+use std::{collections::{HashMap, HashSet}, rc::Rc};
 use endlessgrid::Grid;
 use glam::Vec2;
-use crate::{spawn, Body, Context, Entity, Event, Map, MapResult, Tile};
+use crate::{chunk_of, chunk_tiles, entity_tiles, spawn, AssetError, Body, Bounds, Context, Entity, Event, EventKind, Ghost, Map, MapResult, MapTile, Message, Tile};
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mode {
+    #[default]
+    Story,
+    TimeTrial,
+    /// two players, both must reach the goal
+    Coop,
+    /// two players, first to reach the goal wins
+    Versus,
+}
+
+impl Mode {
+    pub fn players(&self) -> usize {
+        match self {
+            Mode::Story | Mode::TimeTrial => 1,
+            Mode::Coop | Mode::Versus => 2,
+        }
+    }
+}
+
+/// Chunks around each player whose tiles are loaded into `Game::grid`.
+pub const STREAM_RADIUS: i32 = 2;
 
 #[derive(Default)]
 pub struct Game {
@@ -9,26 +32,39 @@ pub struct Game {
     pub level_current: u32,
     pub map_next: String,
     pub map_current: Option<Rc<dyn Map>>,
+    /// Entity tiles of `map_current`, found once when it is loaded and spawned on every restart.
+    pub map_entities: Vec<((i32, i32), MapTile)>,
+    /// Tiles of the chunks in `chunks_loaded`, see [`Game::stream_chunks`].
     pub grid: Grid<Tile>,
-    pub grid_width: u32,
-    pub grid_height: u32,
+    pub chunks_loaded: HashSet<(i32, i32)>,
+    pub bounds: Bounds,
     pub entities: HashMap<u32, Entity>,
-    pub center_text: String,
-    pub player:u32,
+    pub center_text: Option<Message>,
     pub events:Vec<Event>,
     pub pause:bool,
     pub elapsed_total_sec:f32,
     pub lives_extra:i32,
     pub coins:u32,
-    pub skin_chosen:u32,
-    pub next_id:u32
+    pub skins_chosen:Vec<u32>,
+    pub next_id:u32,
+    pub mode:Mode,
+    pub level_elapsed_sec:f32,
+    pub ghost:Option<Rc<Ghost>>,
+    pub ghost_recording:Ghost,
+    pub players_at_goal:u32,
+    pub wins:Vec<u32>
 }
 
 impl Game {
     pub fn init(&mut self, ctx: &mut dyn Context) {
-        let _ = ctx;
         self.lives_extra = 3;
-        self.map_next = ctx.map_list().first().expect("").clone();
+        match ctx.map_list().first() {
+            Some(map) => self.map_next = map.clone(),
+            None => {
+                let error = AssetError::new("maps.txt", "the map list is empty");
+                self.events.push(EventKind::Error(error).into());
+            }
+        }
     }
 
     pub fn next_level(&mut self, ctx: &mut dyn Context) {
@@ -38,7 +74,7 @@ impl Game {
             self.level_current = next_map;
             self.map_next = map.clone();
         } else {
-            self.events.push(Event::GameOver { score: self.score });
+            self.events.push(EventKind::GameOver { score: self.score }.into());
         }
     }
 
@@ -53,13 +89,14 @@ impl Game {
                     if cell.is_block {
                         v.push(Body::Block(index, cell));
                     }
-                } else if index.0 < 0 || index.0 >= self.grid_width as i32 {
+                } else if index.0 < self.bounds.min.0 || index.0 >= self.bounds.max.0 {
                     v.push(Body::Void(index));
                 }
             }
         }
 
-        for e in self.entities.values() {
+        // players do not clip against each other
+        for e in self.entities.values().filter(|e| !e.is_player) {
             match e.clip {
                 crate::Clip::Clip => {
                     let body = Body::Entity(e);
@@ -75,74 +112,138 @@ impl Game {
         if whole_game {
             *self = Game {
                 lives_extra:3,
-                map_next:ctx.map_list().first().expect("").clone(),
-                skin_chosen:self.skin_chosen,
+                map_next:ctx.map_list().first().cloned().unwrap_or_default(),
+                skins_chosen:self.skins_chosen.clone(),
+                mode:self.mode,
                 ..Default::default()
             };
         } else {
             *self = Game {
                 level_current:self.level_current,
                 map_current:self.map_current.take(),
+                map_entities:std::mem::take(&mut self.map_entities),
                 score:self.score,
                 elapsed_total_sec:self.elapsed_total_sec,
                 coins:self.coins,
                 lives_extra:self.lives_extra,
-                skin_chosen:self.skin_chosen,
+                skins_chosen:self.skins_chosen.clone(),
+                mode:self.mode,
+                wins:self.wins.clone(),
                 ..Default::default()
             };
         }
-        let _ = ctx;
-       
+        if self.mode == Mode::TimeTrial
+            && let Some(map_name) = ctx.map_list().get(self.level_current as usize).cloned()
+        {
+            self.ghost = ctx.ghost(&map_name);
+        }
+
         if let Some(map) = self.map_current.clone() {
-            for y in 0..map.height() {
-                for x in 0..map.width() {
-                    let tile = map.tile(x as i32, y as i32);
-                    if !tile.is_entity {
-                        self.grid.insert(
-                            (x as i32, y as i32),
-                            Tile {
-                                is_block: tile.is_block,
-                                variant: tile.variant,
-                                is_foreground: tile.is_foreground,
-                                is_deadly: tile.is_deadly,
-                            },
-                        );
+            self.bounds = map.bounds();
+            // entities are spawned once for the whole map, tiles are streamed in by `stream_chunks`
+            let entities = std::mem::take(&mut self.map_entities);
+            for (index, tile) in &entities {
+                let pos = Vec2::new(index.0 as f32 + 0.5, index.1 as f32 + 0.5);
+                if tile.is_player {
+                    for index in 0..self.mode.players() {
+                        spawn::spawn_player(self, pos, index as u32);
                     }
-
-                    if tile.is_player {
-                        spawn::spawn_player(self, Vec2::new(x as f32 + 0.5, y as f32 + 0.5));
+                    if self.ghost.is_some() {
+                        spawn::spawn_ghost(self, pos);
                     }
+                }
 
-                    if tile.is_goal {
-                        spawn::spawn_goal(self, Vec2::new(x as f32 + 0.5, y as f32 + 0.5));
-                    }
+                if tile.is_goal {
+                    spawn::spawn_goal(self, pos);
+                }
 
-                    if tile.is_coin {
-                        spawn::spawn_coin(self, Vec2::new(x as f32 + 0.5, y as f32 + 0.5));
-                    }
+                if tile.is_coin {
+                    spawn::spawn_coin(self, pos);
+                }
 
-                    if tile.is_cloud {
-                        spawn::spawn_cloud(self, Vec2::new(x as f32 + 0.5, y as f32 + 0.5));
-                    }
+                if tile.is_cloud {
+                    spawn::spawn_cloud(self, pos);
                 }
             }
-            self.grid_width = map.width();
-            self.grid_height = map.height();
+            self.map_entities = entities;
             self.map_current = Some(map);
+            self.stream_chunks();
+        }
+    }
+
+    /// Loads the tiles of the chunks near the players and the midpoint between them into
+    /// `grid` and drops the chunks far from all of those, so the size of the world does not matter.
+    pub fn stream_chunks(&mut self) {
+        let Some(map) = self.map_current.clone() else {
+            return;
+        };
+        let chunk_at = |pos: Vec2| chunk_of((pos.x.floor() as i32, pos.y.floor() as i32));
+        let mut centers: Vec<(i32, i32)> = self.players().map(|e| chunk_at(e.pos)).collect();
+        if centers.is_empty() {
+            return;
+        }
+        // the camera looks at the midpoint, which is far from both players when they split up
+        centers.push(chunk_at(self.players_midpoint()));
+        let distance = |chunk: (i32, i32)| {
+            centers.iter().map(|c| (chunk.0 - c.0).abs().max((chunk.1 - c.1).abs())).min().unwrap_or(i32::MAX)
+        };
+
+        // chunks are kept a little beyond the radius so walking back and forth does not reload them
+        let far: Vec<(i32, i32)> = self.chunks_loaded.iter().copied().filter(|c| distance(*c) > STREAM_RADIUS + 1).collect();
+        if !far.is_empty() {
+            for chunk in &mut self.grid {
+                if far.contains(&chunk_of(chunk.top_left())) {
+                    chunk.clear();
+                }
+            }
+            for chunk in far {
+                self.chunks_loaded.remove(&chunk);
+            }
+        }
+
+        for center in centers.iter() {
+            for dy in -STREAM_RADIUS..=STREAM_RADIUS {
+                for dx in -STREAM_RADIUS..=STREAM_RADIUS {
+                    let chunk = (center.0 + dx, center.1 + dy);
+                    if !self.chunks_loaded.insert(chunk) {
+                        continue;
+                    }
+                    for index in chunk_tiles(chunk).filter(|index| self.bounds.contains(*index)) {
+                        let tile = map.tile(index.0, index.1);
+                        if !tile.is_entity {
+                            self.grid.insert(
+                                index,
+                                Tile {
+                                    is_block: tile.is_block,
+                                    variant: tile.variant,
+                                    tileset: tile.tileset,
+                                    flip_x: tile.flip_x,
+                                    flip_y: tile.flip_y,
+                                    flip_d: tile.flip_d,
+                                    is_foreground: tile.is_foreground,
+                                    is_deadly: tile.is_deadly,
+                                },
+                            );
+                        }
+                    }
+                }
+            }
         }
     }
 
     pub fn update(&mut self, ctx: &mut dyn Context) {
         if !self.map_next.is_empty() {
             match ctx.map(&self.map_next) {
-                MapResult::NotFound => {
-                    println!("failed to find map with name {}", self.map_next);
+                MapResult::Error(error) => {
+                    self.events.push(EventKind::Error(error).into());
                     self.map_next = "".to_string();
+                    return;
                 }
                 MapResult::Pending => {
                     return;
                 }
                 MapResult::Ok(map) => {
+                    self.map_entities = entity_tiles(map.as_ref());
                     self.map_current = Some(map);
                     self.map_next = "".to_string();
                     self.restart(ctx, false);
@@ -155,17 +256,36 @@ impl Game {
             let Some(mut e) = self.entities.remove(&id) else {
                 continue;
             };
+            e.anim_elapsed_sec += ctx.dt();
             (e.update)(&mut e, self, ctx);
             if !e.delete_me {
                 self.entities.insert(id, e);
             }
         }
         
+        self.stream_chunks();
+
         if !self.pause {
             self.elapsed_total_sec += ctx.dt();
+            self.level_elapsed_sec += ctx.dt();
         }
     }
 
+    pub fn players(&self) -> impl Iterator<Item = &Entity> {
+        self.entities.values().filter(|e| e.is_player)
+    }
+
+    /// Returns the midpoint between the players, which the camera follows.
+    pub fn players_midpoint(&self) -> Vec2 {
+        let players = self.players().count().max(1);
+        self.players().map(|e| e.pos).sum::<Vec2>() / players as f32
+    }
+
+    /// Returns the skin chosen by `player`.
+    pub fn skin(&self, player: u32) -> u32 {
+        self.skins_chosen.get(player as usize).copied().unwrap_or_default()
+    }
+
     pub fn spawn_entity(&mut self) -> &mut Entity {
         self.next_id += 1;
         let uuid = self.next_id;
@@ -178,4 +298,28 @@ impl Game {
         );
         self.entities.get_mut(&uuid).unwrap()
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use crate::{AsciiMap, CHUNK_SIZE};
+    use super::*;
+
+    #[test]
+    fn chunks_stream_around_the_midpoint() {
+        let width = 12 * CHUNK_SIZE as usize + 8;
+        let map = AsciiMap::parse("wide.map", &format!("{}\n{}\n", ".".repeat(width), "#".repeat(width))).unwrap();
+        let mut game = Game { bounds: map.bounds(), map_current: Some(Rc::new(map)), ..Default::default() };
+        spawn::spawn_player(&mut game, Vec2::new(1.5, 0.5), 0);
+        spawn::spawn_player(&mut game, Vec2::new(width as f32 - 1.5, 0.5), 1);
+        game.stream_chunks();
+
+        let midpoint = game.players_midpoint();
+        assert_eq!(chunk_of((midpoint.x as i32, 1)), (6, 0));
+        for chunk in [(0, 0), (2, 0), (4, 0), (6, 0), (8, 0), (10, 0), (12, 0)] {
+            assert!(game.chunks_loaded.contains(&chunk), "{chunk:?}");
+        }
+        assert!(game.grid.get((midpoint.x as i32, 1)).is_some_and(|tile| tile.is_block));
+        // between the reach of the players and of the midpoint
+        assert!(!game.chunks_loaded.contains(&(3, 0)) && !game.chunks_loaded.contains(&(9, 0)));
+    }
+}
@@ -0,0 +1,165 @@
+use glam::Vec2;
+
+use crate::DirX;
+
+pub const GHOST_TICK_RATE: f32 = 60.0;
+/// Version written in the first line of ghost files, newer versions are read as far as
+/// they are understood so saved ghosts keep working across game versions.
+pub const GHOST_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug)]
+pub struct GhostSample {
+    pub pos: Vec2,
+    pub dir_x: DirX,
+    pub skin: u32,
+}
+
+/// A recorded run of the player sampled at a fixed tick rate.
+///
+/// The text format starts with a `ghost <version>` line followed by
+/// `key value` header lines and a `samples` line, after which each line is a
+/// comma separated `x, y, dir_x, skin` sample. Unknown header keys and extra
+/// sample columns are ignored, so they can be added without a new version.
+#[derive(Clone, Debug)]
+pub struct Ghost {
+    pub tick_rate: f32,
+    pub samples: Vec<GhostSample>,
+}
+
+impl Default for Ghost {
+    fn default() -> Self {
+        Self {
+            tick_rate: GHOST_TICK_RATE,
+            samples: Vec::default(),
+        }
+    }
+}
+
+impl Ghost {
+    /// Records `sample` for every tick elapsed since the last recorded one.
+    pub fn record(&mut self, elapsed_sec: f32, sample: GhostSample) {
+        let ticks = (elapsed_sec * self.tick_rate) as usize + 1;
+        while self.samples.len() < ticks {
+            self.samples.push(sample);
+        }
+    }
+
+    /// Returns the sample at `elapsed_sec`, holding the last sample when the run is over.
+    pub fn sample(&self, elapsed_sec: f32) -> Option<&GhostSample> {
+        let tick = (elapsed_sec * self.tick_rate) as usize;
+        self.samples.get(tick).or(self.samples.last())
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut s = format!("ghost {GHOST_VERSION}\n");
+        s += &format!("tick_rate {}\n", self.tick_rate);
+        s += "samples\n";
+        for sample in self.samples.iter() {
+            let dir_x = match sample.dir_x {
+                DirX::Left => "L",
+                DirX::Right => "R",
+            };
+            s += &format!("{},{},{},{}\n", sample.pos.x, sample.pos.y, dir_x, sample.skin);
+        }
+        s
+    }
+
+    /// Parses a ghost written by [`Ghost::to_text`] of this or a later version.
+    ///
+    /// Sample lines that cannot be read repeat the sample before them, or the first
+    /// readable one when they lead the run, so the rest of the run keeps its timing.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines();
+        let version = lines.next().and_then(|l| l.strip_prefix("ghost ")).ok_or("not a ghost file")?.trim();
+        if !version.parse::<u32>().is_ok_and(|v| v >= 1) {
+            return Err(format!("unknown ghost version '{version}'"));
+        }
+        let mut ghost = Ghost::default();
+        for line in lines.by_ref() {
+            let mut cols = line.split_whitespace();
+            match (cols.next(), cols.next()) {
+                (Some("samples"), _) => break,
+                (Some("tick_rate"), Some(v)) => ghost.tick_rate = v.parse().map_err(|_| format!("invalid tick rate '{v}'"))?,
+                _ => {}
+            }
+        }
+        if ghost.tick_rate <= 0.0 {
+            return Err(format!("invalid tick rate '{}'", ghost.tick_rate));
+        }
+        // bad lines before the first readable sample are filled in once it is found
+        let mut leading = 0;
+        for line in lines {
+            match parse_sample(line).or(ghost.samples.last().copied()) {
+                Some(sample) => {
+                    ghost.samples.extend(std::iter::repeat_n(sample, leading + 1));
+                    leading = 0;
+                }
+                None => leading += 1,
+            }
+        }
+        Ok(ghost)
+    }
+}
+
+fn parse_sample(line: &str) -> Option<GhostSample> {
+    let mut cols = line.split(",").map(str::trim);
+    let x = cols.next()?.parse().ok()?;
+    let y = cols.next()?.parse().ok()?;
+    let dir_x = match cols.next() {
+        Some("L") => DirX::Left,
+        _ => DirX::Right,
+    };
+    let skin = cols.next().and_then(|s| s.parse().ok()).unwrap_or_default();
+    Some(GhostSample {
+        pos: Vec2::new(x, y),
+        dir_x,
+        skin,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut ghost = Ghost::default();
+        ghost.record(0.0, GhostSample { pos: Vec2::new(1.5, 2.0), dir_x: DirX::Left, skin: 2 });
+        ghost.record(2.0 / GHOST_TICK_RATE, GhostSample { pos: Vec2::new(3.0, -1.25), dir_x: DirX::Right, skin: 2 });
+        assert_eq!(ghost.samples.len(), 3);
+
+        let parsed = Ghost::parse(&ghost.to_text()).unwrap();
+        assert_eq!(parsed.tick_rate, ghost.tick_rate);
+        assert_eq!(parsed.samples.len(), ghost.samples.len());
+        for (a, b) in parsed.samples.iter().zip(ghost.samples.iter()) {
+            assert_eq!(a.pos, b.pos);
+            assert_eq!(a.skin, b.skin);
+            assert_eq!(matches!(a.dir_x, DirX::Left), matches!(b.dir_x, DirX::Left));
+        }
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        assert!(Ghost::parse("ghost 0\nsamples\n1,2,L,0\n").is_err());
+        assert!(Ghost::parse("ghost x\nsamples\n1,2,L,0\n").is_err());
+        assert!(Ghost::parse("samples\n1,2,L,0\n").is_err());
+    }
+
+    #[test]
+    fn newer_versions_are_read() {
+        let ghost = Ghost::parse("ghost 3\ntick_rate 30\nmap res/maps/map01.tmx\nsamples\n1,2,L,0,extra\n3,4,R,1\n").unwrap();
+        assert_eq!(ghost.tick_rate, 30.0);
+        assert_eq!(ghost.samples.len(), 2);
+        assert_eq!(ghost.samples[1].pos, Vec2::new(3.0, 4.0));
+    }
+
+    #[test]
+    fn bad_lines_hold_the_previous_sample() {
+        let ghost = Ghost::parse("ghost 1\ntick_rate 30\nsamples\nnonsense\n\n1,2,L,0\n???\n3,4,R,1\n").unwrap();
+        assert_eq!(ghost.tick_rate, 30.0);
+        let xs: Vec<f32> = ghost.samples.iter().map(|s| s.pos.x).collect();
+        assert_eq!(xs, [1.0, 1.0, 1.0, 1.0, 3.0]);
+        assert_eq!(ghost.sample(4.0 / 30.0).map(|s| s.pos.x), Some(3.0));
+        assert!(Ghost::parse("ghost 1\nsamples\nnonsense\n").unwrap().samples.is_empty());
+    }
+}
@@ -23,5 +23,17 @@ pub use event::*;
 mod map;
 pub use map::*;
 
+mod ascii;
+pub use ascii::*;
+
+mod message;
+pub use message::*;
+
+mod error;
+pub use error::*;
+
+mod ghost;
+pub use ghost::*;
+
 pub mod spawn;
 pub mod update;
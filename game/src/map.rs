@@ -1,12 +1,81 @@
+/// Side of the square chunks maps are streamed in, the same as the chunks of `Game::grid`.
+pub const CHUNK_SIZE: i32 = endlessgrid::CHUNK_SIZE as i32;
+
+/// The tiles a map covers, from `min` up to but not including `max`.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Bounds {
+    pub min: (i32, i32),
+    pub max: (i32, i32),
+}
+
+impl Bounds {
+    pub fn width(&self) -> u32 {
+        (self.max.0 - self.min.0).max(0) as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        (self.max.1 - self.min.1).max(0) as u32
+    }
+
+    pub fn contains(&self, index: (i32, i32)) -> bool {
+        (self.min.0..self.max.0).contains(&index.0) && (self.min.1..self.max.1).contains(&index.1)
+    }
+
+    /// The chunks overlapping the bounds.
+    pub fn chunks(&self) -> impl Iterator<Item = (i32, i32)> + use<> {
+        let min = chunk_of(self.min);
+        let max = chunk_of((self.max.0 - 1, self.max.1 - 1));
+        (min.1..=max.1).flat_map(move |y| (min.0..=max.0).map(move |x| (x, y)))
+    }
+}
+
+/// Returns the chunk a tile is in.
+pub fn chunk_of(index: (i32, i32)) -> (i32, i32) {
+    (index.0.div_euclid(CHUNK_SIZE), index.1.div_euclid(CHUNK_SIZE))
+}
+
+/// Returns the tiles of a chunk.
+pub fn chunk_tiles(chunk: (i32, i32)) -> impl Iterator<Item = (i32, i32)> {
+    let (x0, y0) = (chunk.0 * CHUNK_SIZE, chunk.1 * CHUNK_SIZE);
+    (y0..y0 + CHUNK_SIZE).flat_map(move |y| (x0..x0 + CHUNK_SIZE).map(move |x| (x, y)))
+}
+
+/// Returns the entity tiles of a map, such as the player and coins, so they can be spawned
+/// without searching every tile of the map again.
+pub fn entity_tiles(map: &dyn Map) -> Vec<((i32, i32), MapTile)> {
+    let bounds = map.bounds();
+    let mut tiles = Vec::default();
+    for chunk in map.chunks() {
+        for (x, y) in chunk_tiles(chunk).filter(|index| bounds.contains(*index)) {
+            let tile = map.tile(x, y);
+            if tile.is_entity {
+                tiles.push(((x, y), tile));
+            }
+        }
+    }
+    tiles
+}
 
 pub trait Map {
     fn background(&self) -> (u8, u8, u8);
     fn width(&self) -> u32;
     fn height(&self) -> u32;
     fn tile(&self, x: i32, y: i32) -> MapTile;
+    /// The tiles the map covers, which may start at negative coordinates.
+    fn bounds(&self) -> Bounds {
+        Bounds { min: (0, 0), max: (self.width() as i32, self.height() as i32) }
+    }
+    /// The chunks that have tiles, only these are searched for entities.
+    fn chunks(&self) -> Vec<(i32, i32)> {
+        self.bounds().chunks().collect()
+    }
+    /// Path of the music track looped while the map is played.
+    fn music(&self) -> Option<String> {
+        None
+    }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct MapTile {
     pub is_player: bool,
     pub is_goal: bool,
@@ -17,4 +86,56 @@ pub struct MapTile {
     pub is_coin:bool,
     pub is_deadly:bool,
     pub variant: u32,
+    /// Which of the frontend's tilesets `variant` is an index into.
+    pub tileset: u32,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// Swaps the x and y axes before the other flips, together with them it rotates by 90°.
+    pub flip_d: bool,
+}
+
+impl MapTile {
+    /// Sets the flag named by `kind`, such as `block` or `coin`, unknown kinds are ignored.
+    pub fn set_kind(&mut self, kind: &str) {
+        match kind {
+            "block" => self.is_block = true,
+            "player" => self.is_player = true,
+            "goal" => self.is_goal = true,
+            "foreground" => self.is_foreground = true,
+            "entity" => self.is_entity = true,
+            "coin" => self.is_coin = true,
+            "deadly" => self.is_deadly = true,
+            "cloud" => self.is_cloud = true,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_of_negative_tiles() {
+        assert_eq!(chunk_of((0, 0)), (0, 0));
+        assert_eq!(chunk_of((CHUNK_SIZE - 1, CHUNK_SIZE)), (0, 1));
+        assert_eq!(chunk_of((-1, -1)), (-1, -1));
+        assert_eq!(chunk_of((-CHUNK_SIZE, -CHUNK_SIZE - 1)), (-1, -2));
+    }
+
+    #[test]
+    fn chunk_tiles_negative_chunk() {
+        let tiles: Vec<(i32, i32)> = chunk_tiles((-1, -2)).collect();
+        assert_eq!(tiles.len(), (CHUNK_SIZE * CHUNK_SIZE) as usize);
+        assert_eq!(tiles.first(), Some(&(-CHUNK_SIZE, -2 * CHUNK_SIZE)));
+        assert_eq!(tiles.last(), Some(&(-1, -CHUNK_SIZE - 1)));
+        assert!(tiles.iter().all(|index| chunk_of(*index) == (-1, -2)));
+    }
+
+    #[test]
+    fn bounds_chunks_negative() {
+        let bounds = Bounds { min: (-CHUNK_SIZE - 1, -1), max: (1, CHUNK_SIZE) };
+        let chunks: Vec<(i32, i32)> = bounds.chunks().collect();
+        assert_eq!(chunks, [(-2, -1), (-1, -1), (0, -1), (-2, 0), (-1, 0), (0, 0)]);
+    }
 }
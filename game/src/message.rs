@@ -0,0 +1,20 @@
+/// A user facing text, as a message id and its arguments.
+///
+/// The game only says what to show, the frontend looks `id` up in the player's
+/// language and puts `args` in place of `{0}`, `{1}` and so on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    pub id: &'static str,
+    pub args: Vec<String>,
+}
+
+impl Message {
+    pub fn new(id: &'static str) -> Self {
+        Self { id, args: Vec::default() }
+    }
+
+    pub fn arg(mut self, arg: impl ToString) -> Self {
+        self.args.push(arg.to_string());
+        self
+    }
+}
@@ -2,15 +2,16 @@ use glam::Vec2;
 
 use crate::{update, Clip, Entity, EntityVariant, Game};
 
-pub fn spawn_player(game:&mut Game, pos:Vec2) -> &mut Entity {
-    let skin = game.skin_chosen;
+pub fn spawn_player(game:&mut Game, pos:Vec2, index:u32) -> &mut Entity {
+    let skin = game.skin(index);
     let e = game.spawn_entity();
     e.is_player = true;
     e.pos = pos;
     e.pos_start = e.pos;
     e.update = update::update_player_starting;
     e.variant = EntityVariant::Player {
-        skin
+        skin,
+        index
     };
     e.timer0.start(1.0);
     e
@@ -36,6 +37,19 @@ pub fn spawn_goal(game:&mut Game, pos:Vec2) -> &mut Entity {
     e
 }
 
+pub fn spawn_ghost(game:&mut Game, pos:Vec2) -> &mut Entity {
+    let skin = game.skin(0);
+    let e = game.spawn_entity();
+    e.pos = pos;
+    e.pos_start = e.pos;
+    e.variant = EntityVariant::Ghost {
+        skin
+    };
+    e.update = update::update_ghost;
+    e.clip = Clip::NoClip;
+    e
+}
+
 pub fn spawn_cloud(game:&mut Game, pos:Vec2) -> &mut Entity {
     let e = game.spawn_entity();
     e.pos = pos;
@@ -2,6 +2,10 @@
 #[derive(Default, Clone)]
 pub struct Tile {
     pub variant: u32,
+    pub tileset: u32,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    pub flip_d: bool,
     pub is_block: bool,
     pub is_foreground: bool,
     pub is_deadly:bool
@@ -1,4 +1,4 @@
-use crate::{Body, Context, DirX, Entity, Event, Game, Keys};
+use crate::{AnimState, Body, Context, DirX, Entity, EntityVariant, Event, EventKind, Game, GhostSample, Keys, Message, Mode};
 use cliplib::ClipMoveResult;
 use glam::Vec2;
 use std::{cell::RefCell, f32::consts::PI};
@@ -66,67 +66,79 @@ pub fn update_coin(e: &mut Entity, game: &mut Game, ctx: &mut dyn Context) {
     let a = f32::sin(game.elapsed_total_sec * PI * 2.0);
     e.pos.y = e.pos_start.y + a * d;
 
-    if let Some(player) = game.entities.get(&game.player) {
-        let v = player.pos - e.pos;
-        if v.length() < 1.0 {
-            e.delete_me = true;
-            game.events.push(Event::PickupCoin);
-            game.score += 100;
-            game.coins += 1;
-            if game.coins >= 100 {
-                game.coins = 0;
-                game.lives_extra += 1;
-                game.events.push(Event::PickupExtraLife);
-            }
+    if game.players().any(|player| (player.pos - e.pos).length() < 1.0) {
+        e.delete_me = true;
+        game.events.push(Event::at(EventKind::PickupCoin, e));
+        game.score += 100;
+        game.coins += 1;
+        if game.coins >= 100 {
+            game.coins = 0;
+            game.lives_extra += 1;
+            game.events.push(Event::at(EventKind::PickupExtraLife, e));
         }
     }
 }
 
 pub fn update_player_starting(e: &mut Entity, game: &mut Game, ctx: &mut dyn Context) {
-    game.pause = true;
-    game.center_text = format!("LEVEL {}", game.level_current + 1);
+    // the first player drives the level intro for everyone
+    let leader = e.player_index() == Some(0);
+    if leader {
+        game.pause = true;
+        game.center_text = Some(Message::new("level").arg(game.level_current + 1));
+    }
 
     if e.timer0.tick(ctx.dt()) {
-        game.center_text.clear();
         e.update = update_player;
-        game.pause = false;
+        if leader {
+            game.center_text = None;
+            game.pause = false;
+            game.events.push(EventKind::LevelStarted.into());
+        }
     }
 }
 
 pub fn update_player(e: &mut Entity, game: &mut Game, ctx: &mut dyn Context) {
-    game.player = e.id;
+    if game.pause {
+        return;
+    }
+    let index = e.player_index().unwrap_or_default();
+    let input = ctx.input(index);
+    let d_pad = input.d_pad;
+    let jump_pressed = input.is_key_pressed(Keys::Space);
+    let jump_down = input.is_key_down(Keys::Space);
     apply_gravity(e, ctx.dt());
-    let d_pad = ctx.d_pad();
     let move_speed = 8.0;
     let jump_speed = 20.0;
     let drag_speed = 20.0;
 
-    if ctx.is_key_pressed(Keys::Space)
+    if jump_pressed
         && e.is_touching_floor {
             e.vel.y = -jump_speed;
-            game.events.push(Event::PlayerJump);
+            game.events.push(Event::at(EventKind::PlayerJump, e));
         }
-    if !ctx.is_key_down(Keys::Space)
+    if !jump_down
         && e.vel.y < 0.0 {
             e.vel.y = 0.0;
         }
 
-    let dx = d_pad.x * move_speed * ctx.dt() * 16.0;
+    // partial tilt of an analog stick gives a slower walk
+    let max_speed = move_speed * d_pad.x.abs().min(1.0);
+    let dx = d_pad.x.signum() * move_speed * ctx.dt() * 16.0;
     if d_pad.x < 0.0 {
         e.dir_x = DirX::Left;
-        if e.vel.x > -move_speed {
+        if e.vel.x > -max_speed {
             e.vel.x += dx;
         }
-        if e.vel.x < -move_speed {
-            e.vel.x = -move_speed;
+        if e.vel.x < -max_speed {
+            e.vel.x = -max_speed;
         }
     } else if d_pad.x > 0.0 {
         e.dir_x = DirX::Right;
-        if e.vel.x < move_speed {
+        if e.vel.x < max_speed {
             e.vel.x += dx;
         }
-        if e.vel.x > move_speed {
-            e.vel.x = move_speed;
+        if e.vel.x > max_speed {
+            e.vel.x = max_speed;
         }
     } else {
         let s = e.vel.x.abs() * ctx.dt() * drag_speed;
@@ -158,44 +170,95 @@ pub fn update_player(e: &mut Entity, game: &mut Game, ctx: &mut dyn Context) {
         }
         Body::Void(_) => {}
     });
+    e.set_anim(AnimState::from_motion(e.vel, e.is_touching_floor));
 
     if *goal_touched.borrow() {
+        e.set_anim(AnimState::Won);
+        match game.mode {
+            Mode::Coop => {
+                // wait at the goal for the other player
+                game.players_at_goal += 1;
+                if game.players_at_goal < game.mode.players() as u32 {
+                    e.update = update_player_at_goal;
+                    return;
+                }
+                game.center_text = Some(Message::new("you_won"));
+            }
+            Mode::Versus => {
+                if game.wins.len() <= index as usize {
+                    game.wins.resize(index as usize + 1, 0);
+                }
+                game.wins[index as usize] += 1;
+                game.center_text = Some(Message::new("player_wins").arg(index + 1));
+            }
+            Mode::Story | Mode::TimeTrial => {
+                game.center_text = Some(Message::new("you_won"));
+            }
+        }
         // won!
-        game.center_text = "YOU WON!".to_string();
+        game.pause = true;
         e.update = update_player_won;
         e.timer0.start(2.0);
-        game.events.push(Event::Won);
+        game.events.push(Event::at(EventKind::Won, e));
         game.score += 1000 * (game.level_current + 1);
         return;
     }
-    if e.pos.y > game.grid_height as f32 + 1.0 {
+    if e.pos.y > game.bounds.max.1 as f32 + 1.0 {
         dead = true;
     }
 
+    if index == 0 && game.mode == Mode::TimeTrial {
+        game.ghost_recording.record(
+            game.level_elapsed_sec,
+            GhostSample {
+                pos: e.pos,
+                dir_x: e.dir_x,
+                skin: game.skin(index),
+            },
+        );
+    }
+
     if dead {
         // died!
-        game.center_text = "YOU DIED!".to_string();
+        if game.mode != Mode::Versus {
+            game.center_text = Some(Message::new("you_died"));
+        }
         e.update = update_player_dead;
+        e.set_anim(AnimState::Dead);
         e.timer0.start(2.0);
-        game.events.push(Event::Died);
+        game.events.push(Event::at(EventKind::Died, e));
     }
 }
 
 pub fn update_player_dead(e: &mut Entity, game: &mut Game, ctx: &mut dyn Context) {
+    if game.mode == Mode::Versus {
+        // only the player who died is held back, then respawns at the start
+        if e.timer0.tick(ctx.dt()) {
+            e.pos = e.pos_start;
+            e.vel = Vec2::ZERO;
+            e.set_anim(AnimState::Idle);
+            e.update = update_player;
+        }
+        return;
+    }
     game.pause = true;
     if e.timer0.tick(ctx.dt()) {
         e.delete_me = true;
-        let whole_game = game.lives_extra == 0;
+        let whole_game = game.lives_extra == 0 && game.mode != Mode::TimeTrial;
         let score = game.score;
         game.restart(ctx, whole_game);
-        if !whole_game {
+        if whole_game {
+            game.events.push(EventKind::GameOver { score }.into());
+        } else if game.mode != Mode::TimeTrial {
             game.lives_extra -= 1;
-        } else {
-            game.events.push(Event::GameOver { score });
         }
     }
 }
 
+pub fn update_player_at_goal(e: &mut Entity, game: &mut Game, ctx: &mut dyn Context) {
+    let _ = (e, game, ctx);
+}
+
 pub fn update_player_won(e: &mut Entity, game: &mut Game, ctx: &mut dyn Context) {
     game.pause = true;
     if e.timer0.tick(ctx.dt()) {
@@ -203,13 +266,24 @@ pub fn update_player_won(e: &mut Entity, game: &mut Game, ctx: &mut dyn Context)
     }
 }
 
-pub fn update_cloud(e: &mut Entity, game: &mut Game, ctx: &mut dyn Context) {
-    let player_standing_on_cloud = if let Some(player) = game.entities.get(&game.player) {
-        let v = player.pos - e.pos;
-        v.length() < 1.0 && player.is_touching_floor
-    } else {
-        false
+pub fn update_ghost(e: &mut Entity, game: &mut Game, ctx: &mut dyn Context) {
+    let Some(sample) = game.ghost.as_ref().and_then(|g| g.sample(game.level_elapsed_sec)) else {
+        return;
     };
+    // the recording has no velocity, derive it from the movement since last frame
+    if ctx.dt() > 0.0 {
+        let vel = (sample.pos - e.pos) / ctx.dt();
+        e.set_anim(AnimState::from_motion(vel, vel.y.abs() < 0.01));
+    }
+    e.pos = sample.pos;
+    e.dir_x = sample.dir_x;
+    e.variant = EntityVariant::Ghost { skin: sample.skin };
+}
+
+pub fn update_cloud(e: &mut Entity, game: &mut Game, ctx: &mut dyn Context) {
+    let player_standing_on_cloud = game
+        .players()
+        .any(|player| (player.pos - e.pos).length() < 1.0 && player.is_touching_floor);
 
     let cloud_is_gone = e.pos_start != e.pos;
     let cloud_gone_sec = 0.5;
@@ -221,6 +295,7 @@ pub fn update_cloud(e: &mut Entity, game: &mut Game, ctx: &mut dyn Context) {
         }
     } else if player_standing_on_cloud {
         if e.timer0.tick(ctx.dt()) {
+            game.events.push(Event::at(EventKind::CloudCrumble, e));
             e.pos = Vec2::new(-2.0, -2.0);
             e.timer0.start(cloud_reappear_sec);
         }
@@ -228,3 +303,122 @@ pub fn update_cloud(e: &mut Entity, game: &mut Game, ctx: &mut dyn Context) {
         e.timer0.start(cloud_gone_sec);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use crate::{AsciiMap, Ghost, Input, MapResult};
+    use super::*;
+
+    const DT: f32 = 1.0 / 60.0;
+
+    /// A frontend that always has `map` loaded and gives every player `input`.
+    struct TestContext {
+        map: Rc<AsciiMap>,
+        maps: Vec<String>,
+        input: Input,
+    }
+
+    impl Context for TestContext {
+        fn map(&mut self, _name: &str) -> MapResult { MapResult::Ok(self.map.clone()) }
+        fn dt(&self) -> f32 { DT }
+        fn d_pad(&self) -> Vec2 { self.input.d_pad }
+        fn is_key_down(&self, key: Keys) -> bool { self.input.is_key_down(key) }
+        fn is_key_pressed(&self, key: Keys) -> bool { self.input.is_key_pressed(key) }
+        fn is_any_key_pressed(&self) -> bool { false }
+        fn input(&self, _player: u32) -> &Input { &self.input }
+        fn map_list(&self) -> &Vec<String> { &self.maps }
+        fn rand_f32(&self) -> f32 { 0.5 }
+        fn ghost(&mut self, _map_name: &str) -> Option<Rc<Ghost>> { None }
+    }
+
+    /// Loads `map` and plays until the level intro is over.
+    fn start(map: &str) -> (Game, TestContext) {
+        let map = AsciiMap::parse("test.map", map).unwrap();
+        let mut ctx = TestContext { map: Rc::new(map), maps: vec!["test.map".to_string()], input: Input::default() };
+        let mut game = Game::default();
+        game.init(&mut ctx);
+        for _ in 0..(2.0 / DT) as u32 {
+            game.update(&mut ctx);
+            if happened(&game, |k| matches!(k, EventKind::LevelStarted)) {
+                return (game, ctx);
+            }
+        }
+        panic!("the level did not start");
+    }
+
+    fn run(game: &mut Game, ctx: &mut TestContext, secs: f32) {
+        for _ in 0..(secs / DT) as u32 {
+            game.update(ctx);
+        }
+    }
+
+    fn player(game: &Game) -> &Entity {
+        game.players().next().expect("no player")
+    }
+
+    fn happened(game: &Game, kind: fn(&EventKind) -> bool) -> bool {
+        game.events.iter().any(|e| kind(&e.kind))
+    }
+
+    #[test]
+    fn player_lands_on_blocks() {
+        let (mut game, mut ctx) = start(".....\n..P..\n.....\n#####\n");
+        run(&mut game, &mut ctx, 1.0);
+        let player = player(&game);
+        assert!(player.is_touching_floor);
+        assert!((player.pos.y - 2.55).abs() < 0.01, "{}", player.pos);
+        assert_eq!(player.pos.x, 2.5);
+        assert!(!happened(&game, |k| matches!(k, EventKind::Died)));
+    }
+
+    #[test]
+    fn walls_stop_the_player() {
+        let (mut game, mut ctx) = start(".......\n.P..#..\n#######\n");
+        ctx.input.d_pad = Vec2::X;
+        run(&mut game, &mut ctx, 2.0);
+        let player = player(&game);
+        assert!((player.pos.x - 3.55).abs() < 0.01, "{}", player.pos);
+        assert!(player.is_touching_floor);
+    }
+
+    #[test]
+    fn player_jumps_onto_a_ledge() {
+        let (mut game, mut ctx) = start("......\n......\n....##\n.P....\n######\n");
+        ctx.input.keys_down.insert(Keys::Space);
+        ctx.input.keys_pressed.insert(Keys::Space);
+        ctx.input.d_pad = Vec2::X;
+        run(&mut game, &mut ctx, 0.1);
+        ctx.input.keys_pressed.clear();
+        run(&mut game, &mut ctx, 1.5);
+        let player = player(&game);
+        assert!(happened(&game, |k| matches!(k, EventKind::PlayerJump)));
+        assert!(player.is_touching_floor && (player.pos.y - 1.55).abs() < 0.01, "{}", player.pos);
+    }
+
+    #[test]
+    fn spikes_kill_the_player() {
+        let (mut game, mut ctx) = start(".....\n.P...\n#^###\n");
+        run(&mut game, &mut ctx, 0.5);
+        assert!(happened(&game, |k| matches!(k, EventKind::Died)));
+        assert_eq!(game.center_text, Some(Message::new("you_died")));
+    }
+
+    #[test]
+    fn falling_out_of_the_map_kills_the_player() {
+        let (mut game, mut ctx) = start("...\n.P.\n...\n");
+        run(&mut game, &mut ctx, 2.0);
+        assert!(happened(&game, |k| matches!(k, EventKind::Died)));
+    }
+
+    #[test]
+    fn reaching_the_goal_wins() {
+        let (mut game, mut ctx) = start(".......\n.P.c.G.\n#######\n");
+        ctx.input.d_pad = Vec2::X;
+        run(&mut game, &mut ctx, 2.0);
+        assert!(happened(&game, |k| matches!(k, EventKind::PickupCoin)));
+        assert!(happened(&game, |k| matches!(k, EventKind::Won)));
+        assert!(game.pause);
+        assert_eq!(game.score, 100 + 1000);
+    }
+}
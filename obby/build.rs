@@ -0,0 +1,49 @@
+//! Writes the list of files compiled into the executable by the `embed`
+//! feature to `$OUT_DIR/embedded.rs`, see `src/vfs.rs`.
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+fn collect(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    if env::var_os("CARGO_FEATURE_EMBED").is_some() {
+        let root = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("..");
+        let res = root.join("res");
+        println!("cargo:rerun-if-changed={}", res.display());
+        let mut files = Vec::default();
+        collect(&res, &mut files);
+        files.sort();
+        let mut list = String::from("&[\n");
+        for file in &files {
+            // keyed like the loose files, e.g. `res/maps.txt`
+            let name = file
+                .strip_prefix(&root)
+                .unwrap()
+                .to_string_lossy()
+                .replace('\\', "/");
+            let abs = file.canonicalize().unwrap().display().to_string();
+            list += &format!("    ({name:?}, include_bytes!({abs:?})),\n");
+        }
+        list += "]\n";
+        fs::write(
+            Path::new(&env::var("OUT_DIR").unwrap()).join("embedded.rs"),
+            list,
+        )
+        .unwrap();
+    }
+}
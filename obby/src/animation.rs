@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+
+use game::AnimState;
+use tiled::{PropertyValue, Tileset};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Frame {
+    pub tile: u32,
+    pub duration_sec: f32,
+}
+
+/// Tile animations read from a Tiled tileset.
+///
+/// Frames come from the `<animation>` Tiled stores per tile. A tile can name
+/// the tile to show for each `AnimState` with an int property called after the
+/// state, e.g. `run = 140`, and the animation of that tile is then played.
+#[derive(Default)]
+pub struct Animations {
+    frames: HashMap<u32, Vec<Frame>>,
+    states: HashMap<(u32, AnimState), u32>,
+}
+
+impl Animations {
+    pub fn from_tileset(tileset: &Tileset) -> Self {
+        let mut animations = Self::default();
+        for (id, tile) in tileset.tiles() {
+            if let Some(frames) = &tile.animation {
+                let frames = frames
+                    .iter()
+                    .map(|f| Frame {
+                        tile: f.tile_id,
+                        duration_sec: f.duration as f32 / 1000.0,
+                    })
+                    .collect();
+                animations.frames.insert(id, frames);
+            }
+            for state in AnimState::ALL {
+                if let Some(PropertyValue::IntValue(state_tile)) = tile.properties.get(state.name())
+                {
+                    animations.states.insert((id, state), *state_tile as u32);
+                }
+            }
+        }
+        animations
+    }
+
+    /// Returns the tile to animate for `tile` in `state`, or `tile` itself.
+    pub fn state_tile(&self, tile: u32, state: AnimState) -> u32 {
+        self.states.get(&(tile, state)).copied().unwrap_or(tile)
+    }
+
+    /// Returns the tile shown `elapsed_sec` into the looping animation of `tile`.
+    pub fn frame(&self, tile: u32, elapsed_sec: f32) -> u32 {
+        let Some(frames) = self.frames.get(&tile) else {
+            return tile;
+        };
+        let total_sec: f32 = frames.iter().map(|f| f.duration_sec).sum();
+        if total_sec <= 0.0 {
+            return tile;
+        }
+        let mut t = elapsed_sec.rem_euclid(total_sec);
+        for frame in frames {
+            if t < frame.duration_sec {
+                return frame.tile;
+            }
+            t -= frame.duration_sec;
+        }
+        frames.last().map(|f| f.tile).unwrap_or(tile)
+    }
+}
@@ -1,50 +1,120 @@
 use std::{
     collections::HashMap,
-    io::{Cursor, Error},
+    io::{Cursor, Error, ErrorKind},
+    path::{Component, Path},
     rc::Rc,
 };
 
-use game::MapResult;
-use macroquad::{audio::Sound, file::load_file, math::Rect, texture::Texture2D};
+use game::{AssetError, MapResult};
+use macroquad::{
+    math::Rect,
+    texture::{Image, Texture2D},
+};
+
+use crate::{Map, animation::Animations, ldtk, loader::Loader, sfx::Sfx, text::Text};
 
-use crate::Map;
+/// The tileset the sprites of entities are drawn from, maps may use it and others.
+pub const TILESET_TSX: &str = "res/maps/tileset.tsx";
+
+/// Index of the atlas of [`TILESET_TSX`] in [`Assets::atlases`].
+pub const ENTITY_ATLAS: usize = 0;
 
 pub struct Atlas {
     pub col: u16,
     pub rows: u16,
     pub texture: Texture2D,
+    pub animations: Animations,
+    /// Pixels between the border of the texture and the tiles.
+    pub padding: f32,
+    /// Pixels between neighbouring tiles.
+    pub spacing: f32,
 }
 
 impl Atlas {
     pub fn new(col: u16, rows: u16, texture: Texture2D) -> Self {
         texture.set_filter(macroquad::texture::FilterMode::Nearest);
-        Self { col, rows, texture }
+        Self {
+            col,
+            rows,
+            texture,
+            animations: Animations::default(),
+            padding: 0.0,
+            spacing: 0.0,
+        }
+    }
+
+    pub fn with_spacing(mut self, padding: f32, spacing: f32) -> Self {
+        self.padding = padding;
+        self.spacing = spacing;
+        self
+    }
+
+    pub fn with_animations(mut self, animations: Animations) -> Self {
+        self.animations = animations;
+        self
     }
 
     pub fn index(&self, index: f32) -> Rect {
-        let w = self.texture.width() / self.col as f32;
-        let h = self.texture.height() / self.rows as f32;
-        let idx = index as u16;
-        let col = idx % self.col;
-        let row = idx / self.col;
-        let x = col as f32 * w;
-        let y = row as f32 * h;
-        Rect { x, y, w, h }
+        let size = (self.texture.width(), self.texture.height());
+        grid_rect(
+            size,
+            (self.col, self.rows),
+            self.padding,
+            self.spacing,
+            index as u16,
+        )
     }
 }
 
 pub struct Assets {
+    pub loader: Loader,
     pub maps: HashMap<String, MapResult>,
-    pub maps_pending: Vec<String>,
-    pub tileset: Atlas,
-    pub sfx: HashMap<String, Vec<Sound>>,
+    maps_pending: Vec<PendingMap>,
+    /// One atlas per tileset, [`ENTITY_ATLAS`] is the one entities are drawn from.
+    pub atlases: Vec<Atlas>,
+    /// Index into `atlases` of each tileset seen so far, see [`tileset_key`].
+    tilesets: HashMap<String, usize>,
+    pub sfx: Sfx,
+    pub text: Text,
+}
+
+impl Default for Assets {
+    fn default() -> Self {
+        Self {
+            loader: Loader::default(),
+            maps: HashMap::default(),
+            maps_pending: Vec::default(),
+            atlases: vec![Atlas::new(1, 1, Texture2D::empty())],
+            tilesets: HashMap::default(),
+            sfx: Sfx::default(),
+            text: Text::default_font(),
+        }
+    }
+}
+
+struct PendingMap {
+    path: String,
+    /// Files to wait for before trying to parse the map again.
+    waiting: Vec<String>,
+}
+
+/// Outcome of trying to parse a map with the files fetched so far.
+enum MapLoad {
+    Done(Result<Rc<dyn game::Map>, AssetError>),
+    Waiting(Vec<String>),
 }
 
-struct TiledReader {
-    pub resources: HashMap<String, Rc<[u8]>>,
+/// Serves the files a Tiled map or tileset refers to from the loader.
+///
+/// Files that have not been fetched yet are noted in `missing` so they can be
+/// requested and the parse retried once they arrive.
+struct TiledReader<'a> {
+    loader: &'a Loader,
+    missing: Vec<String>,
 }
-impl tiled::ResourceReader for TiledReader {
-    type Resource = Cursor<Rc<[u8]>>;
+
+impl<'a> tiled::ResourceReader for TiledReader<'a> {
+    type Resource = Cursor<&'a [u8]>;
 
     type Error = Error;
 
@@ -52,65 +122,284 @@ impl tiled::ResourceReader for TiledReader {
         &mut self,
         path: &std::path::Path,
     ) -> std::result::Result<Self::Resource, Self::Error> {
-        let path = path.to_str().unwrap_or_default().replace("\\", "/");
-        match self.resources.get(&path) {
-            Some(res) => {
-                return Result::Ok(Cursor::new(res.clone()));
-            }
+        let path = normalize(path);
+        match self.loader.get(&path) {
+            Some(Ok(bytes)) => Ok(Cursor::new(bytes)),
+            Some(Err(err)) => Err(Error::other(err)),
             None => {
-                return Result::Err(std::io::Error::new(
-                    std::io::ErrorKind::NotFound,
-                    "file was not found",
-                ));
+                self.missing.push(path);
+                Err(Error::new(ErrorKind::NotFound, "file was not fetched yet"))
+            }
+        }
+    }
+}
+
+/// Resolves `.` and `..` so paths Tiled joins, like `res/maps/../imgs/tileset.png`,
+/// match the paths files are fetched by.
+pub fn normalize(path: &Path) -> String {
+    let mut parts: Vec<&str> = Vec::default();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                parts.pop();
             }
+            Component::Normal(part) => parts.push(part.to_str().unwrap_or_default()),
+            _ => {}
         }
     }
+    parts.join("/")
+}
+
+/// Returns the rect of tile `index` of an image of `size` split into a grid of `(columns, rows)`.
+fn grid_rect(size: (f32, f32), grid: (u16, u16), padding: f32, spacing: f32, index: u16) -> Rect {
+    let gaps = 2.0 * padding - spacing;
+    let w = (size.0 - gaps) / grid.0 as f32 - spacing;
+    let h = (size.1 - gaps) / grid.1 as f32 - spacing;
+    let col = index % grid.0;
+    let row = index / grid.0;
+    let x = padding + col as f32 * (w + spacing);
+    let y = padding + row as f32 * (h + spacing);
+    Rect { x, y, w, h }
+}
+
+/// Identifies a tileset across maps, embedded tilesets share the path of their map.
+fn tileset_key(tileset: &tiled::Tileset) -> String {
+    format!("{}#{}", normalize(&tileset.source), tileset.name)
+}
+
+fn parse_json<T: serde::de::DeserializeOwned>(path: &str, bytes: &[u8]) -> Result<T, AssetError> {
+    serde_json::from_slice(bytes).map_err(|err| AssetError::new(path, err))
+}
+
+/// Decodes an image fetched by `loader` into a texture.
+pub fn load_texture(path: &str, loader: &Loader) -> Result<Texture2D, AssetError> {
+    let bytes = loader.bytes(path)?;
+    let image =
+        Image::from_file_with_format(bytes, None).map_err(|err| AssetError::new(path, err))?;
+    Ok(Texture2D::from_image(&image))
+}
+
+/// Loads a Tiled tileset fetched by `loader` on its own, outside of any map.
+fn load_tileset(path: &str, loader: &Loader) -> Result<tiled::Tileset, AssetError> {
+    let reader = TiledReader {
+        loader,
+        missing: Vec::default(),
+    };
+    tiled::Loader::with_reader(reader)
+        .load_tsx_tileset(path)
+        .map_err(|err| AssetError::new(path, err))
+}
+
+/// Creates the atlas of a tileset whose image has been fetched by `loader`.
+fn load_atlas(tileset: &tiled::Tileset, loader: &Loader) -> Result<Atlas, AssetError> {
+    let Some(image) = &tileset.image else {
+        let path = normalize(&tileset.source);
+        return Err(AssetError::new(
+            path,
+            "image collection tilesets are not supported",
+        ));
+    };
+    let texture = load_texture(&normalize(&image.source), loader)?;
+    let columns = tileset.columns.max(1);
+    let rows = tileset.tilecount.div_ceil(columns).max(1);
+    let animations = Animations::from_tileset(tileset);
+    Ok(Atlas::new(columns as u16, rows as u16, texture)
+        .with_animations(animations)
+        .with_spacing(tileset.margin as f32, tileset.spacing as f32))
 }
 
 impl Assets {
+    /// Returns the map at `path`, starting to fetch it the first time it is asked for.
+    ///
+    /// Asking for a map before it is needed preloads it.
     pub fn load_map(&mut self, path: &str) -> MapResult {
         if !self.maps.contains_key(path) {
+            let (file, _) = ldtk::split_path(path);
             self.maps.insert(path.to_string(), MapResult::Pending);
-            self.maps_pending.push(path.to_string());
+            self.maps_pending.push(PendingMap {
+                path: path.to_string(),
+                waiting: vec![file.to_string()],
+            });
+            self.loader.request(file);
         }
 
         let map_result = self.maps.get(path).unwrap();
         match map_result {
-            MapResult::NotFound => MapResult::NotFound,
+            MapResult::Error(error) => MapResult::Error(error.clone()),
             MapResult::Pending => MapResult::Pending,
             MapResult::Ok(map) => MapResult::Ok(map.clone()),
         }
     }
 
-    pub async fn load_pending(&mut self) {
-        // load maps
-        for path in self.maps_pending.drain(..) {
-            let mut resources = HashMap::new();
-            resources.insert(
-                "res/maps/tileset.tsx".to_string(),
-                load_file("res/maps/tileset.tsx")
-                    .await
-                    .unwrap()
-                    .into_boxed_slice()
-                    .into(),
-            );
-            resources.insert(
-                path.clone(),
-                load_file(&path).await.unwrap().into_boxed_slice().into(),
-            );
-            let mut loader = tiled::Loader::with_reader(TiledReader { resources });
-
-            let map = loader.load_tmx_map(&path);
-            match map {
-                Ok(map) => {
-                    let map = Map { tiled_map: map };
-                    let res = game::MapResult::Ok(Rc::new(map));
-                    self.maps.insert(path.to_string(), res);
+    /// Decodes [`TILESET_TSX`] into the atlas at [`ENTITY_ATLAS`], maps using it share that atlas.
+    pub fn load_entity_tileset(&mut self) -> Result<(), AssetError> {
+        let tileset = load_tileset(TILESET_TSX, &self.loader)?;
+        self.atlases[ENTITY_ATLAS] = load_atlas(&tileset, &self.loader)?;
+        self.tilesets.insert(tileset_key(&tileset), ENTITY_ATLAS);
+        Ok(())
+    }
+
+    /// Returns the index of the atlas known as `key`, creating it with `load` the first time.
+    fn add_atlas(
+        &mut self,
+        key: String,
+        load: impl FnOnce(&Loader) -> Result<Atlas, AssetError>,
+    ) -> Result<usize, AssetError> {
+        if let Some(index) = self.tilesets.get(&key) {
+            return Ok(*index);
+        }
+        self.atlases.push(load(&self.loader)?);
+        self.tilesets.insert(key, self.atlases.len() - 1);
+        Ok(self.atlases.len() - 1)
+    }
+
+    fn add_tileset(&mut self, tileset: &tiled::Tileset) -> Result<usize, AssetError> {
+        self.add_atlas(tileset_key(tileset), |loader| load_atlas(tileset, loader))
+    }
+
+    /// Collects fetched files and parses the maps whose files have all arrived.
+    ///
+    /// Parsing a map tells which tilesets, templates and images it refers to, so
+    /// a map is retried as those arrive until nothing is missing.
+    pub fn load_pending(&mut self) {
+        self.loader.update();
+        let mut i = 0;
+        while i < self.maps_pending.len() {
+            let pending = &self.maps_pending[i];
+            if pending.waiting.iter().any(|p| self.loader.get(p).is_none()) {
+                i += 1;
+                continue;
+            }
+            let path = pending.path.clone();
+            // maps are read by the format their file extension tells
+            let file = ldtk::split_path(&path).0;
+            let load = if file.ends_with(".ldtk") {
+                self.load_ldtk_map(&path)
+            } else if file.ends_with(".map") {
+                let map = self.loader.text(&path);
+                let map = map.and_then(|text| game::AsciiMap::parse(&path, text));
+                MapLoad::Done(map.map(|map| Rc::new(map) as _))
+            } else {
+                self.load_tiled_map(&path)
+            };
+            let res = match load {
+                MapLoad::Waiting(waiting) => {
+                    for p in &waiting {
+                        self.loader.request(p);
+                    }
+                    self.maps_pending[i].waiting = waiting;
+                    i += 1;
+                    continue;
                 }
-                Err(_) => {
-                    self.maps.insert(path.to_owned(), game::MapResult::NotFound);
+                MapLoad::Done(Ok(map)) => MapResult::Ok(map),
+                MapLoad::Done(Err(error)) => {
+                    println!("{error}");
+                    MapResult::Error(error)
                 }
-            }
+            };
+            self.maps_pending.swap_remove(i);
+            self.maps.insert(path, res);
         }
     }
+
+    /// Parses a Tiled map along with the files it refers to and the atlases of its tilesets.
+    fn load_tiled_map(&mut self, path: &str) -> MapLoad {
+        let mut loader = tiled::Loader::with_reader(TiledReader {
+            loader: &self.loader,
+            missing: Vec::default(),
+        });
+        let res = loader.load_tmx_map(path);
+        let missing = std::mem::take(&mut loader.reader_mut().missing);
+        let tiled_map = match res {
+            Ok(tiled_map) => tiled_map,
+            Err(_) if !missing.is_empty() => return MapLoad::Waiting(missing),
+            Err(err) => return MapLoad::Done(Err(AssetError::new(path, err))),
+        };
+
+        let images: Vec<String> = tiled_map
+            .tilesets()
+            .iter()
+            .filter(|tileset| !self.tilesets.contains_key(&tileset_key(tileset)))
+            .filter_map(|tileset| tileset.image.as_ref())
+            .map(|image| normalize(&image.source))
+            .filter(|image| self.loader.get(image).is_none())
+            .collect();
+        if !images.is_empty() {
+            return MapLoad::Waiting(images);
+        }
+
+        let atlases: Result<Vec<usize>, AssetError> = tiled_map
+            .tilesets()
+            .iter()
+            .map(|tileset| self.add_tileset(tileset))
+            .collect();
+        MapLoad::Done(atlases.map(|atlases| Rc::new(Map { tiled_map, atlases }) as _))
+    }
+
+    /// Parses a level of an LDtk project along with the atlases of the tilesets it draws from.
+    fn load_ldtk_map(&mut self, path: &str) -> MapLoad {
+        let (file, level_id) = ldtk::split_path(path);
+        let dir = Path::new(file).parent().unwrap_or(Path::new(""));
+        let project: ldtk::Project = match self.loader.bytes(file).and_then(|b| parse_json(file, b))
+        {
+            Ok(project) => project,
+            Err(error) => return MapLoad::Done(Err(error)),
+        };
+        let level = match ldtk::find_level(&project, level_id) {
+            Ok(level) => level,
+            Err(err) => return MapLoad::Done(Err(AssetError::new(path, err))),
+        };
+        let external: Option<ldtk::Level> = match ldtk::external_level(level, dir) {
+            Some(level_file) => match self.loader.get(&level_file) {
+                None => return MapLoad::Waiting(vec![level_file]),
+                Some(bytes) => match bytes.and_then(|b| parse_json(&level_file, b)) {
+                    Ok(external) => Some(external),
+                    Err(error) => return MapLoad::Done(Err(error)),
+                },
+            },
+            None => None,
+        };
+        let level = external.as_ref().unwrap_or(level);
+
+        let images: Vec<String> = ldtk::tileset_images(level, dir)
+            .into_iter()
+            .filter(|image| !self.tilesets.contains_key(image) && self.loader.get(image).is_none())
+            .collect();
+        if !images.is_empty() {
+            return MapLoad::Waiting(images);
+        }
+
+        let map = ldtk::LdtkMap::parse(path, &project, level, dir, |image, def| {
+            self.add_atlas(image.to_string(), |loader| {
+                let texture = load_texture(image, loader)?;
+                let (columns, rows) = (def.columns.max(1), def.rows.max(1));
+                Ok(Atlas::new(columns as u16, rows as u16, texture)
+                    .with_spacing(def.padding as f32, def.spacing as f32))
+            })
+        });
+        MapLoad::Done(map.map(|map| Rc::new(map) as _))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_rects() {
+        assert_eq!(
+            grid_rect((640.0, 640.0), (20, 20), 0.0, 0.0, 21),
+            Rect::new(32.0, 32.0, 32.0, 32.0)
+        );
+        // 2px around the image and 1px between three columns and two rows of 16px tiles
+        let size = (2.0 + 16.0 * 3.0 + 2.0 + 2.0, 2.0 + 16.0 * 2.0 + 1.0 + 2.0);
+        assert_eq!(
+            grid_rect(size, (3, 2), 2.0, 1.0, 0),
+            Rect::new(2.0, 2.0, 16.0, 16.0)
+        );
+        assert_eq!(
+            grid_rect(size, (3, 2), 2.0, 1.0, 5),
+            Rect::new(36.0, 19.0, 16.0, 16.0)
+        );
+    }
 }
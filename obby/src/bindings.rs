@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+
+use game::Keys;
+use gamepads::Button;
+use macroquad::input::KeyCode;
+
+use crate::storage::Storage;
+
+const BINDINGS_KEY: &str = "controls.cfg";
+
+/// Actions that can be bound, with their name in the config file.
+pub const ACTIONS: [(Keys, &str); 8] = [
+    (Keys::Space, "jump"),
+    (Keys::Left, "left"),
+    (Keys::Right, "right"),
+    (Keys::Up, "up"),
+    (Keys::Down, "down"),
+    (Keys::Confirm, "confirm"),
+    (Keys::Back, "back"),
+    (Keys::Pause, "pause"),
+];
+
+/// Actions read while playing and while in menus, a binding may be shared
+/// between the two groups, e.g. the up arrow both jumps and moves menu focus.
+const GAMEPLAY_ACTIONS: [Keys; 4] = [Keys::Space, Keys::Left, Keys::Right, Keys::Pause];
+const MENU_ACTIONS: [Keys; 6] = [
+    Keys::Up,
+    Keys::Down,
+    Keys::Left,
+    Keys::Right,
+    Keys::Confirm,
+    Keys::Back,
+];
+
+fn conflicts(a: Keys, b: Keys) -> bool {
+    a != b
+        && ((GAMEPLAY_ACTIONS.contains(&a) && GAMEPLAY_ACTIONS.contains(&b))
+            || (MENU_ACTIONS.contains(&a) && MENU_ACTIONS.contains(&b)))
+}
+
+/// Binding profiles, single player and the two halves of the keyboard for two players.
+pub const PROFILES: [&str; 3] = ["solo", "p1", "p2"];
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Binding {
+    Key(KeyCode),
+    Button(Button),
+}
+
+impl Binding {
+    pub fn name(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("key:{key:?}"),
+            Binding::Button(button) => format!("pad:{button:?}"),
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().split_once(":")? {
+            ("key", name) => key_from_name(name).map(Binding::Key),
+            ("pad", name) => Button::all()
+                .find(|b| format!("{b:?}") == name)
+                .map(Binding::Button),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! key_names {
+    ($($key:ident),* $(,)?) => {
+        fn key_from_name(name: &str) -> Option<KeyCode> {
+            match name {
+                $(stringify!($key) => Some(KeyCode::$key),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+key_names!(
+    Space, Apostrophe, Comma, Minus, Period, Slash, Key0, Key1, Key2, Key3, Key4, Key5, Key6,
+    Key7, Key8, Key9, Semicolon, Equal, A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S,
+    T, U, V, W, X, Y, Z, LeftBracket, Backslash, RightBracket, GraveAccent, World1, World2,
+    Escape, Enter, Tab, Backspace, Insert, Delete, Right, Left, Down, Up, PageUp, PageDown, Home,
+    End, CapsLock, ScrollLock, NumLock, PrintScreen, Pause, F1, F2, F3, F4, F5, F6, F7, F8, F9,
+    F10, F11, F12, Kp0, Kp1, Kp2, Kp3, Kp4, Kp5, Kp6, Kp7, Kp8, Kp9, KpDecimal, KpDivide,
+    KpMultiply, KpSubtract, KpAdd, KpEnter, KpEqual, LeftShift, LeftControl, LeftAlt,
+    LeftSuper, RightShift, RightControl, RightAlt, RightSuper, Menu, Back,
+);
+
+/// Keyboard keys and gamepad buttons bound to each action.
+#[derive(Clone, Default)]
+pub struct ActionMap {
+    pub bindings: HashMap<Keys, Vec<Binding>>,
+}
+
+impl ActionMap {
+    fn new(bindings: &[(Keys, &[Binding])]) -> Self {
+        Self {
+            bindings: bindings.iter().map(|(k, b)| (*k, b.to_vec())).collect(),
+        }
+    }
+
+    pub fn get(&self, action: Keys) -> &[Binding] {
+        self.bindings.get(&action).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Binds `binding` to `action`, moving it away from any action it conflicts with.
+    pub fn bind(&mut self, action: Keys, binding: Binding) {
+        for (other, bindings) in self.bindings.iter_mut() {
+            if conflicts(action, *other) {
+                bindings.retain(|b| *b != binding);
+            }
+        }
+        let bindings = self.bindings.entry(action).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn clear(&mut self, action: Keys) {
+        self.bindings.remove(&action);
+    }
+}
+
+pub const DEADZONE_DEFAULT: f32 = 0.2;
+
+/// Input bindings for every profile, loaded from and saved to `controls.cfg`.
+///
+/// Each line of the config is `profile, action, binding, binding, ...` where a
+/// binding is `key:<KeyCode>` or `pad:<Button>`, e.g. `solo, jump, key:Space, pad:ActionDown`.
+/// A `deadzone, 0.2` line sets the radial deadzone of the analog sticks.
+#[derive(Clone)]
+pub struct Bindings {
+    pub profiles: [ActionMap; 3],
+    pub deadzone: f32,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use Binding::{Button as B, Key as K};
+        let pad_jump = [B(Button::ActionDown), B(Button::ActionRight)];
+        let pad_left = B(Button::DPadLeft);
+        let pad_right = B(Button::DPadRight);
+        let pad_up = B(Button::DPadUp);
+        let pad_down = B(Button::DPadDown);
+        let pad_confirm = B(Button::ActionDown);
+        let pad_back = B(Button::ActionRight);
+        let pad_pause = B(Button::RightCenterCluster);
+        Self {
+            profiles: [
+                ActionMap::new(&[
+                    (Keys::Space, &[K(KeyCode::Space), K(KeyCode::Up), pad_jump[0], pad_jump[1]]),
+                    (Keys::Left, &[K(KeyCode::A), K(KeyCode::Left), pad_left]),
+                    (Keys::Right, &[K(KeyCode::D), K(KeyCode::Right), pad_right]),
+                    (Keys::Up, &[K(KeyCode::W), K(KeyCode::Up), pad_up]),
+                    (Keys::Down, &[K(KeyCode::S), K(KeyCode::Down), pad_down]),
+                    (Keys::Confirm, &[K(KeyCode::Enter), K(KeyCode::Space), pad_confirm]),
+                    (Keys::Back, &[K(KeyCode::Escape), K(KeyCode::Backspace), pad_back]),
+                    (Keys::Pause, &[K(KeyCode::Escape), pad_pause]),
+                ]),
+                ActionMap::new(&[
+                    (Keys::Space, &[K(KeyCode::Space), K(KeyCode::W), pad_jump[0], pad_jump[1]]),
+                    (Keys::Left, &[K(KeyCode::A), pad_left]),
+                    (Keys::Right, &[K(KeyCode::D), pad_right]),
+                    (Keys::Up, &[K(KeyCode::W), pad_up]),
+                    (Keys::Down, &[K(KeyCode::S), pad_down]),
+                    (Keys::Confirm, &[K(KeyCode::Space), pad_confirm]),
+                    (Keys::Back, &[K(KeyCode::Escape), pad_back]),
+                    (Keys::Pause, &[K(KeyCode::Escape), pad_pause]),
+                ]),
+                ActionMap::new(&[
+                    (Keys::Space, &[K(KeyCode::Up), pad_jump[0], pad_jump[1]]),
+                    (Keys::Left, &[K(KeyCode::Left), pad_left]),
+                    (Keys::Right, &[K(KeyCode::Right), pad_right]),
+                    (Keys::Up, &[K(KeyCode::Up), pad_up]),
+                    (Keys::Down, &[K(KeyCode::Down), pad_down]),
+                    (Keys::Confirm, &[K(KeyCode::Enter), pad_confirm]),
+                    (Keys::Back, &[K(KeyCode::Backspace), pad_back]),
+                    (Keys::Pause, &[pad_pause]),
+                ]),
+            ],
+            deadzone: DEADZONE_DEFAULT,
+        }
+    }
+}
+
+impl Bindings {
+    /// Returns the profile used by `player` when `players` are playing.
+    pub fn profile(&self, players: usize, player: usize) -> &ActionMap {
+        if players <= 1 {
+            &self.profiles[0]
+        } else {
+            &self.profiles[(player + 1).min(self.profiles.len() - 1)]
+        }
+    }
+
+    /// Loads bindings on top of the defaults, actions missing from the config keep their default.
+    pub fn load(storage: &dyn Storage) -> Self {
+        let mut bindings = Self::default();
+        let Some(cfg) = storage.load(BINDINGS_KEY) else {
+            return bindings;
+        };
+        for (n, line) in cfg.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut cols = line.split(",").map(str::trim);
+            if line.starts_with("deadzone") {
+                match cols.nth(1).map(str::parse::<f32>) {
+                    Some(Ok(deadzone)) => bindings.deadzone = deadzone.clamp(0.0, 0.95),
+                    _ => println!("{BINDINGS_KEY}:{}: invalid deadzone", n + 1),
+                }
+                continue;
+            }
+            let profile = cols.next().and_then(|p| PROFILES.iter().position(|x| *x == p));
+            let action = cols
+                .next()
+                .and_then(|a| ACTIONS.iter().find(|(_, name)| *name == a));
+            let (Some(profile), Some((action, _))) = (profile, action) else {
+                println!("{BINDINGS_KEY}:{}: unknown profile or action", n + 1);
+                continue;
+            };
+            let map = &mut bindings.profiles[profile];
+            map.clear(*action);
+            for col in cols {
+                match Binding::parse(col) {
+                    Some(binding) => map.bind(*action, binding),
+                    None => println!("{BINDINGS_KEY}:{}: unknown binding '{col}'", n + 1),
+                }
+            }
+        }
+        bindings
+    }
+
+    pub fn save(&self, storage: &mut dyn Storage) {
+        let mut s = format!("deadzone, {}\n", self.deadzone);
+        for (profile, map) in PROFILES.iter().zip(self.profiles.iter()) {
+            for (action, name) in ACTIONS.iter() {
+                s += &format!("{profile}, {name}");
+                for binding in map.get(*action) {
+                    s += &format!(", {}", binding.name());
+                }
+                s += "\n";
+            }
+        }
+        storage.save(BINDINGS_KEY, &s);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn binding_names_round_trip() {
+        for binding in [Binding::Key(KeyCode::LeftBracket), Binding::Button(Button::DPadUp)] {
+            assert_eq!(Binding::parse(&binding.name()), Some(binding));
+        }
+        assert_eq!(Binding::parse(" key:Space "), Some(Binding::Key(KeyCode::Space)));
+    }
+
+    #[test]
+    fn unknown_names_are_rejected() {
+        assert_eq!(Binding::parse("key:Nope"), None);
+        assert_eq!(Binding::parse("pad:Nope"), None);
+        assert_eq!(Binding::parse("mouse:Left"), None);
+        assert_eq!(Binding::parse("Space"), None);
+    }
+
+    #[test]
+    fn save_then_load() {
+        let mut bindings = Bindings {
+            deadzone: 0.35,
+            ..Default::default()
+        };
+        bindings.profiles[1].clear(Keys::Left);
+        bindings.profiles[1].bind(Keys::Left, Binding::Key(KeyCode::J));
+        bindings.profiles[2].bind(Keys::Space, Binding::Button(Button::ActionUp));
+        let mut storage = MemoryStorage::default();
+        bindings.save(&mut storage);
+
+        let loaded = Bindings::load(&storage);
+        assert_eq!(loaded.deadzone, 0.35);
+        for (a, b) in loaded.profiles.iter().zip(bindings.profiles.iter()) {
+            for (action, _) in ACTIONS {
+                assert_eq!(a.get(action), b.get(action));
+            }
+        }
+    }
+
+    #[test]
+    fn bad_lines_are_skipped() {
+        let mut storage = MemoryStorage::default();
+        let cfg = "# comment\n\
+            deadzone, lots\n\
+            nobody, jump, key:J\n\
+            solo, fly, key:J\n\
+            solo, left, key:Nope, key:J\n\
+            just some words\n";
+        storage.save(BINDINGS_KEY, cfg);
+
+        let loaded = Bindings::load(&storage);
+        let defaults = Bindings::default();
+        assert_eq!(loaded.deadzone, DEADZONE_DEFAULT);
+        // the unknown key is dropped and the rest of the line still applies
+        assert_eq!(loaded.profiles[0].get(Keys::Left), [Binding::Key(KeyCode::J)]);
+        assert_eq!(loaded.profiles[0].get(Keys::Space), defaults.profiles[0].get(Keys::Space));
+        assert_eq!(loaded.profiles[1].get(Keys::Space), defaults.profiles[1].get(Keys::Space));
+    }
+}
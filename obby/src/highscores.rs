@@ -0,0 +1,154 @@
+use crate::storage::Storage;
+
+const HIGH_SCORES_KEY: &str = "high_scores.csv";
+pub const HIGH_SCORES_MAX: usize = 10;
+
+#[derive(Clone)]
+pub struct HighScore {
+    pub name: String,
+    pub score: u32,
+}
+
+/// Top ten table of final scores, best first.
+#[derive(Default)]
+pub struct HighScores {
+    pub entries: Vec<HighScore>,
+}
+
+impl HighScores {
+    pub fn load(storage: &dyn Storage) -> Self {
+        let mut entries = Vec::default();
+        for line in storage.load(HIGH_SCORES_KEY).unwrap_or_default().lines() {
+            // names may contain commas, the score is after the last one
+            let Some((name, score)) = line.rsplit_once(',') else {
+                continue;
+            };
+            if let Ok(score) = score.trim().parse() {
+                entries.push(HighScore {
+                    name: name.trim().to_string(),
+                    score,
+                });
+            }
+        }
+        entries.sort_by_key(|e| std::cmp::Reverse(e.score));
+        entries.truncate(HIGH_SCORES_MAX);
+        Self { entries }
+    }
+
+    pub fn save(&self, storage: &mut dyn Storage) {
+        let mut s = String::new();
+        for entry in self.entries.iter() {
+            s += &format!("{}, {}\n", entry.name, entry.score);
+        }
+        storage.save(HIGH_SCORES_KEY, &s);
+    }
+
+    /// Returns true if `score` would make it into the table.
+    pub fn qualifies(&self, score: u32) -> bool {
+        score > 0
+            && (self.entries.len() < HIGH_SCORES_MAX
+                || self.entries.last().is_none_or(|last| score > last.score))
+    }
+
+    /// Inserts the score below any equal scores and returns its rank.
+    pub fn insert(&mut self, name: &str, score: u32) -> usize {
+        let rank = self
+            .entries
+            .iter()
+            .position(|e| score > e.score)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(
+            rank,
+            HighScore {
+                name: name.to_string(),
+                score,
+            },
+        );
+        self.entries.truncate(HIGH_SCORES_MAX);
+        rank
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    fn table(scores: &[u32]) -> HighScores {
+        let mut high_scores = HighScores::default();
+        for (i, score) in scores.iter().enumerate() {
+            high_scores.insert(&format!("P{i}"), *score);
+        }
+        high_scores
+    }
+
+    fn names(high_scores: &HighScores) -> Vec<&str> {
+        high_scores
+            .entries
+            .iter()
+            .map(|e| e.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn insert_below_equal_scores() {
+        let mut high_scores = table(&[300, 200, 200, 100]);
+        assert_eq!(high_scores.insert("NEW", 200), 3);
+        assert_eq!(names(&high_scores), ["P0", "P1", "P2", "NEW", "P3"]);
+        assert_eq!(high_scores.insert("TOP", 400), 0);
+        assert_eq!(high_scores.insert("LAST", 50), 6);
+    }
+
+    #[test]
+    fn table_is_cut_to_the_max() {
+        let mut high_scores = table(&[100; HIGH_SCORES_MAX]);
+        assert_eq!(high_scores.insert("NEW", 500), 0);
+        assert_eq!(high_scores.entries.len(), HIGH_SCORES_MAX);
+        assert_eq!(high_scores.entries[0].name, "NEW");
+        assert_eq!(
+            high_scores.entries.last().unwrap().name,
+            format!("P{}", HIGH_SCORES_MAX - 2)
+        );
+    }
+
+    #[test]
+    fn qualifies() {
+        assert!(!HighScores::default().qualifies(0));
+        assert!(HighScores::default().qualifies(1));
+        let full = table(&[100; HIGH_SCORES_MAX]);
+        assert!(!full.qualifies(0));
+        assert!(!full.qualifies(99));
+        assert!(!full.qualifies(100));
+        assert!(full.qualifies(101));
+        assert!(table(&[100; HIGH_SCORES_MAX - 1]).qualifies(1));
+    }
+
+    #[test]
+    fn save_then_load() {
+        let mut storage = MemoryStorage::default();
+        let mut high_scores = table(&[300, 200, 100]);
+        high_scores.insert("A, B", 250);
+        high_scores.save(&mut storage);
+
+        let loaded = HighScores::load(&storage);
+        assert_eq!(names(&loaded), names(&high_scores));
+        let scores: Vec<u32> = loaded.entries.iter().map(|e| e.score).collect();
+        assert_eq!(scores, [300, 250, 200, 100]);
+    }
+
+    #[test]
+    fn bad_lines_are_skipped() {
+        let mut storage = MemoryStorage::default();
+        storage.save(
+            HIGH_SCORES_KEY,
+            "AAA, 100\nnonsense\nBBB, lots\n\nCCC, 300\n",
+        );
+        let loaded = HighScores::load(&storage);
+        assert_eq!(names(&loaded), ["CCC", "AAA"]);
+        assert!(
+            HighScores::load(&MemoryStorage::default())
+                .entries
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use game::{Input, Keys};
+use gamepads::Gamepads;
+use macroquad::prelude::*;
+
+use crate::bindings::{ACTIONS, Binding, Bindings};
+
+/// Applies a radial deadzone, rescaling the remaining range to `0..=1`.
+pub fn apply_deadzone(stick: Vec2, deadzone: f32) -> Vec2 {
+    let len = stick.length();
+    if len <= deadzone || len == 0.0 {
+        return Vec2::ZERO;
+    }
+    let scaled = ((len - deadzone) / (1.0 - deadzone)).min(1.0);
+    stick / len * scaled
+}
+
+/// How far a stick must be tilted to count as a direction in menus.
+const STICK_DIRECTION_MIN: f32 = 0.5;
+
+/// Returns the direction key a stick is tilted towards along its dominant axis, y points down.
+pub fn stick_direction(stick: Vec2) -> Option<Keys> {
+    if stick.length() < STICK_DIRECTION_MIN {
+        return None;
+    }
+    let key = if stick.x.abs() > stick.y.abs() {
+        if stick.x < 0.0 {
+            Keys::Left
+        } else {
+            Keys::Right
+        }
+    } else if stick.y < 0.0 {
+        Keys::Up
+    } else {
+        Keys::Down
+    };
+    Some(key)
+}
+
+fn insert(input: &mut Input, key: Keys, down: bool, pressed: bool) {
+    if down {
+        input.keys_down.insert(key);
+    }
+    if pressed {
+        input.keys_pressed.insert(key);
+    }
+}
+
+/// Polls keyboard and gamepads into one `Input` per local player.
+///
+/// With a single player every device controls player one, otherwise each
+/// player uses the keys of its own binding profile and gamepads are handed
+/// out to the players in turn.
+pub fn poll(inputs: &mut [Input], players: usize, gamepads: &Gamepads, bindings: &Bindings) {
+    let players = players.clamp(1, inputs.len());
+    let directions_before: Vec<Option<Keys>> =
+        inputs.iter().map(|i| stick_direction(i.stick)).collect();
+    for input in inputs.iter_mut() {
+        *input = Input::default();
+    }
+
+    for (player, input) in inputs.iter_mut().enumerate().take(players) {
+        let map = bindings.profile(players, player);
+        for (action, _) in ACTIONS {
+            for binding in map.get(action) {
+                if let Binding::Key(key) = binding {
+                    insert(input, action, is_key_down(*key), is_key_pressed(*key));
+                }
+            }
+        }
+    }
+
+    for (n, gamepad) in gamepads.all().enumerate() {
+        let player = n % players;
+        let map = bindings.profile(players, player);
+        let input = &mut inputs[player];
+        let (x, y) = gamepad.left_stick();
+        // stick y is positive upwards, d_pad y is positive downwards
+        let stick = apply_deadzone(Vec2::new(x, -y), bindings.deadzone);
+        if stick.length() > input.stick.length() {
+            input.stick = stick;
+        }
+        for (action, _) in ACTIONS {
+            for binding in map.get(action) {
+                if let Binding::Button(button) = binding {
+                    insert(
+                        input,
+                        action,
+                        gamepad.is_currently_pressed(*button),
+                        gamepad.is_just_pressed(*button),
+                    );
+                }
+            }
+        }
+    }
+
+    // digital input wins over the stick, the stick keeps its magnitude for partial tilt
+    for input in inputs.iter_mut() {
+        if input.is_key_down(Keys::Left) || input.is_key_pressed(Keys::Left) {
+            input.d_pad.x = -1.0;
+        } else if input.is_key_down(Keys::Right) || input.is_key_pressed(Keys::Right) {
+            input.d_pad.x = 1.0;
+        } else {
+            input.d_pad = input.stick;
+        }
+        input.d_pad = input.d_pad.clamp_length_max(1.0);
+    }
+
+    // the stick moves menu focus like the direction keys, pressed when first tilted that way
+    for (input, before) in inputs.iter_mut().zip(directions_before) {
+        if let Some(key) = stick_direction(input.stick) {
+            insert(input, key, true, before != Some(key));
+        }
+    }
+}
+
+/// Returns the first key or gamepad button pressed this frame, used when rebinding.
+pub fn last_binding_pressed(gamepads: &Gamepads) -> Option<Binding> {
+    if let Some(key) = get_last_key_pressed() {
+        return Some(Binding::Key(key));
+    }
+    gamepads
+        .all()
+        .find_map(|g| g.all_just_pressed().next())
+        .map(Binding::Button)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deadzone_edges() {
+        let deadzone = 0.2;
+        assert_eq!(apply_deadzone(Vec2::ZERO, deadzone), Vec2::ZERO);
+        assert_eq!(apply_deadzone(Vec2::new(0.2, 0.0), deadzone), Vec2::ZERO);
+        assert_eq!(
+            apply_deadzone(Vec2::new(0.0, -1.0), deadzone),
+            Vec2::new(0.0, -1.0)
+        );
+        // diagonals of square gates reach past 1 and are clamped
+        let corner = apply_deadzone(Vec2::new(1.0, 1.0), deadzone);
+        assert!((corner.length() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn deadzone_rescales() {
+        let half = apply_deadzone(Vec2::new(0.6, 0.0), 0.2);
+        assert!((half.x - 0.5).abs() < 1e-6);
+        let just_past = apply_deadzone(Vec2::new(0.0, 0.21), 0.2);
+        assert!(just_past.y > 0.0 && just_past.y < 0.02);
+        assert_eq!(
+            apply_deadzone(Vec2::new(0.5, 0.0), 0.0),
+            Vec2::new(0.5, 0.0)
+        );
+    }
+
+    #[test]
+    fn stick_directions() {
+        assert_eq!(stick_direction(Vec2::new(0.3, 0.0)), None);
+        assert_eq!(stick_direction(Vec2::new(-0.8, 0.2)), Some(Keys::Left));
+        assert_eq!(stick_direction(Vec2::new(0.8, -0.2)), Some(Keys::Right));
+        assert_eq!(stick_direction(Vec2::new(0.2, -0.9)), Some(Keys::Up));
+        assert_eq!(stick_direction(Vec2::new(0.0, 1.0)), Some(Keys::Down));
+    }
+}
@@ -0,0 +1,414 @@
+use std::{collections::HashMap, path::Path};
+
+use game::{AssetError, MapTile};
+use serde::Deserialize;
+
+use crate::assets::normalize;
+
+/// Splits `res/maps/world.ldtk#Level_1` into the project file and the identifier
+/// of the level, without one the first level is played.
+pub fn split_path(path: &str) -> (&str, Option<&str>) {
+    match path.split_once('#') {
+        Some((file, level)) => (file, Some(level)),
+        None => (path, None),
+    }
+}
+
+/// The parts of an LDtk project file that are played, see <https://ldtk.io/json>.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Project {
+    default_grid_size: Option<i64>,
+    #[serde(default)]
+    defs: Defs,
+    #[serde(default)]
+    levels: Vec<Level>,
+}
+
+#[derive(Deserialize, Default)]
+struct Defs {
+    #[serde(default)]
+    layers: Vec<LayerDef>,
+    #[serde(default)]
+    tilesets: Vec<TilesetDef>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LayerDef {
+    uid: i64,
+    #[serde(default)]
+    int_grid_values: Vec<IntGridValue>,
+}
+
+#[derive(Deserialize)]
+struct IntGridValue {
+    value: i64,
+    identifier: Option<String>,
+}
+
+/// How the tiles of a tileset image are laid out.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TilesetDef {
+    uid: i64,
+    /// Pixels between the border of the image and the tiles.
+    #[serde(default)]
+    pub padding: u32,
+    /// Pixels between neighbouring tiles.
+    #[serde(default)]
+    pub spacing: u32,
+    #[serde(rename = "__cWid")]
+    pub columns: u32,
+    #[serde(rename = "__cHei")]
+    pub rows: u32,
+}
+
+/// A level, either inside the project or in a file of its own.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Level {
+    identifier: String,
+    px_wid: i64,
+    px_hei: i64,
+    #[serde(rename = "__bgColor")]
+    bg_color: Option<String>,
+    external_rel_path: Option<String>,
+    /// Missing when the level is saved in `external_rel_path`.
+    layer_instances: Option<Vec<Layer>>,
+    #[serde(default)]
+    field_instances: Vec<Field>,
+}
+
+#[derive(Deserialize)]
+struct Field {
+    #[serde(rename = "__identifier")]
+    identifier: String,
+    #[serde(rename = "__value")]
+    value: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Layer {
+    #[serde(rename = "__type")]
+    kind: String,
+    #[serde(rename = "__gridSize")]
+    grid_size: i64,
+    #[serde(rename = "__cWid")]
+    columns: i64,
+    #[serde(rename = "__tilesetDefUid")]
+    tileset_def_uid: Option<i64>,
+    #[serde(rename = "__tilesetRelPath")]
+    tileset_rel_path: Option<String>,
+    layer_def_uid: i64,
+    #[serde(default)]
+    int_grid_csv: Vec<i64>,
+    #[serde(default)]
+    auto_layer_tiles: Vec<TileInstance>,
+    #[serde(default)]
+    grid_tiles: Vec<TileInstance>,
+    #[serde(default)]
+    entity_instances: Vec<EntityInstance>,
+}
+
+#[derive(Deserialize)]
+struct TileInstance {
+    px: (i64, i64),
+    t: u32,
+    #[serde(default)]
+    f: u8,
+}
+
+#[derive(Deserialize)]
+struct EntityInstance {
+    #[serde(rename = "__identifier")]
+    identifier: String,
+    #[serde(rename = "__grid")]
+    grid: (i64, i64),
+}
+
+/// Returns the level named `id` of a project, or its first level.
+pub fn find_level<'a>(project: &'a Project, id: Option<&str>) -> Result<&'a Level, String> {
+    match id {
+        Some(id) => project
+            .levels
+            .iter()
+            .find(|l| l.identifier == id)
+            .ok_or_else(|| format!("there is no level '{id}'")),
+        None => project
+            .levels
+            .first()
+            .ok_or_else(|| "there are no levels".to_string()),
+    }
+}
+
+/// Path of the file a level is saved in when the project keeps levels in separate files.
+pub fn external_level(level: &Level, dir: &Path) -> Option<String> {
+    if level.layer_instances.is_some() {
+        return None;
+    }
+    let path = level.external_rel_path.as_ref()?;
+    Some(normalize(&dir.join(path)))
+}
+
+/// Paths of the tileset images the layers of a level draw from.
+pub fn tileset_images(level: &Level, dir: &Path) -> Vec<String> {
+    level
+        .layer_instances
+        .iter()
+        .flatten()
+        .filter_map(|layer| layer.tileset_rel_path.as_ref())
+        .map(|path| normalize(&dir.join(path)))
+        .collect()
+}
+
+/// A level of an LDtk project, see <https://ldtk.io>.
+///
+/// IntGrid values set the kinds of their cells by their identifier split at
+/// underscores, e.g. `deadly_block`, and entity instances named `Player`,
+/// `Goal`, `Coin` or `Cloud` become those entities. Cells are drawn with the
+/// tiles of the auto-layers and tile layers, the top-most layer wins.
+pub struct LdtkMap {
+    width: u32,
+    height: u32,
+    background: (u8, u8, u8),
+    music: Option<String>,
+    cells: Vec<MapTile>,
+}
+
+impl LdtkMap {
+    /// Reads `level` of `project`, `atlas` returns the atlas index of a tileset
+    /// image given its path and layout.
+    pub fn parse(
+        path: &str,
+        project: &Project,
+        level: &Level,
+        dir: &Path,
+        mut atlas: impl FnMut(&str, &TilesetDef) -> Result<usize, AssetError>,
+    ) -> Result<Self, AssetError> {
+        let layers = level.layer_instances.as_deref().unwrap_or_default();
+        // cells are as big as the grid of the first IntGrid layer
+        let grid = layers
+            .iter()
+            .find(|l| l.kind == "IntGrid")
+            .or(layers.first())
+            .map(|l| l.grid_size)
+            .or(project.default_grid_size)
+            .filter(|grid| *grid > 0)
+            .ok_or_else(|| AssetError::new(path, "the level has no grid size"))?;
+        let width = (level.px_wid / grid).max(0) as u32;
+        let height = (level.px_hei / grid).max(0) as u32;
+        let mut map = Self {
+            width,
+            height,
+            background: level
+                .bg_color
+                .as_deref()
+                .and_then(game::parse_color)
+                .unwrap_or_default(),
+            music: None,
+            cells: vec![MapTile::default(); (width * height) as usize],
+        };
+
+        for field in &level.field_instances {
+            if field.identifier == "music" {
+                // file paths are relative to the project
+                map.music = field
+                    .value
+                    .as_str()
+                    .map(|music| normalize(&dir.join(music)));
+            }
+        }
+
+        // layers are listed top-most first, so the upper tiles are set last
+        for layer in layers.iter().rev() {
+            let layer_grid = if layer.grid_size > 0 {
+                layer.grid_size
+            } else {
+                grid
+            };
+            // `unit` is how many pixels a step of `pos` is
+            let to_cell = |pos: (i64, i64), unit: i64| (pos.0 * unit / grid, pos.1 * unit / grid);
+
+            let values = int_grid_values(project, layer);
+            let columns = layer.columns.max(1);
+            for (i, value) in layer.int_grid_csv.iter().enumerate() {
+                let Some(kinds) = values.get(value) else {
+                    continue;
+                };
+                let pos = (i as i64 % columns, i as i64 / columns);
+                if let Some(cell) = map.cell_mut(to_cell(pos, layer_grid)) {
+                    for kind in kinds.split('_') {
+                        cell.set_kind(kind);
+                    }
+                }
+            }
+
+            if let Some(image) = &layer.tileset_rel_path {
+                let def = project
+                    .defs
+                    .tilesets
+                    .iter()
+                    .find(|def| Some(def.uid) == layer.tileset_def_uid)
+                    .ok_or_else(|| {
+                        AssetError::new(path, format!("no tileset is defined for {image}"))
+                    })?;
+                let tileset = atlas(&normalize(&dir.join(image)), def)? as u32;
+                for tile in layer.auto_layer_tiles.iter().chain(&layer.grid_tiles) {
+                    let Some(cell) = map.cell_mut(to_cell(tile.px, 1)) else {
+                        continue;
+                    };
+                    cell.variant = tile.t;
+                    cell.tileset = tileset;
+                    cell.flip_x = tile.f & 1 != 0;
+                    cell.flip_y = tile.f & 2 != 0;
+                }
+            }
+
+            for entity in &layer.entity_instances {
+                if let Some(cell) = map.cell_mut(to_cell(entity.grid, layer_grid)) {
+                    cell.is_entity = true;
+                    cell.set_kind(&entity.identifier.to_lowercase());
+                }
+            }
+        }
+        Ok(map)
+    }
+
+    fn cell_mut(&mut self, (x, y): (i64, i64)) -> Option<&mut MapTile> {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return None;
+        }
+        self.cells.get_mut((y * self.width as i64 + x) as usize)
+    }
+}
+
+/// Returns the identifiers of the IntGrid values of a layer by value.
+fn int_grid_values(project: &Project, layer: &Layer) -> HashMap<i64, String> {
+    let def = project
+        .defs
+        .layers
+        .iter()
+        .find(|def| def.uid == layer.layer_def_uid);
+    def.map(|def| def.int_grid_values.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|v| Some((v.value, v.identifier.as_ref()?.to_lowercase())))
+        .collect()
+}
+
+impl game::Map for LdtkMap {
+    fn background(&self) -> (u8, u8, u8) {
+        self.background
+    }
+
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn tile(&self, x: i32, y: i32) -> MapTile {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return MapTile::default();
+        }
+        self.cells[(y as u32 * self.width + x as u32) as usize].clone()
+    }
+
+    fn music(&self) -> Option<String> {
+        self.music.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game::Map;
+
+    use super::*;
+
+    const SAMPLE: &str = include_str!("../../res/maps/sample.ldtk");
+
+    /// Parses `level` of the sample, returning the tilesets asked for along with the map.
+    fn parse(project: &Project, level: Option<&str>) -> (LdtkMap, Vec<(String, TilesetDef)>) {
+        let level = find_level(project, level).unwrap();
+        let mut tilesets = Vec::default();
+        let map = LdtkMap::parse(
+            "sample.ldtk",
+            project,
+            level,
+            Path::new("res/maps"),
+            |image, def| {
+                tilesets.push((image.to_string(), def.clone()));
+                Ok(7)
+            },
+        )
+        .unwrap();
+        (map, tilesets)
+    }
+
+    #[test]
+    fn sample_level() {
+        let project: Project = serde_json::from_str(SAMPLE).unwrap();
+        let (map, tilesets) = parse(&project, None);
+        assert_eq!((map.width(), map.height()), (16, 8));
+        assert_eq!(map.background(), (0x3d, 0x38, 0x46));
+        assert_eq!(map.music(), None);
+        assert_eq!(tilesets.len(), 1);
+        assert_eq!(tilesets[0].0, "res/imgs/tileset.png");
+        assert_eq!((tilesets[0].1.columns, tilesets[0].1.rows), (20, 20));
+
+        let ground = map.tile(0, 7);
+        assert!(ground.is_block && !ground.is_deadly);
+        assert_eq!((ground.variant, ground.tileset), (20, 7));
+        let spikes = map.tile(6, 7);
+        assert!(spikes.is_block && spikes.is_deadly);
+        assert_eq!(spikes.variant, 41);
+        assert!(map.tile(5, 4).flip_x && !map.tile(4, 4).flip_x);
+        assert!(!map.tile(0, 0).is_block);
+
+        let player = map.tile(1, 6);
+        assert!(player.is_entity && player.is_player);
+        assert!(map.tile(14, 6).is_goal && map.tile(4, 3).is_coin && map.tile(10, 1).is_cloud);
+        assert_eq!(game::entity_tiles(&map).len(), 4);
+    }
+
+    #[test]
+    fn tileset_padding_and_spacing() {
+        let sample = SAMPLE
+            .replace("\"spacing\": 0", "\"spacing\": 1")
+            .replace("\"padding\": 0", "\"padding\": 2");
+        let project: Project = serde_json::from_str(&sample).unwrap();
+        let (_, tilesets) = parse(&project, Some("Level_0"));
+        assert_eq!((tilesets[0].1.padding, tilesets[0].1.spacing), (2, 1));
+    }
+
+    #[test]
+    fn levels() {
+        let project: Project = serde_json::from_str(SAMPLE).unwrap();
+        assert!(find_level(&project, Some("Level_0")).is_ok());
+        assert!(find_level(&project, Some("Level_9")).is_err());
+        let level = find_level(&project, None).unwrap();
+        assert_eq!(external_level(level, Path::new("res/maps")), None);
+        assert_eq!(
+            tileset_images(level, Path::new("res/maps")),
+            ["res/imgs/tileset.png"]
+        );
+
+        let external: Level = serde_json::from_str(
+            r#"{"identifier": "Level_1", "pxWid": 64, "pxHei": 32, "externalRelPath": "sample/Level_1.ldtkl"}"#,
+        )
+        .unwrap();
+        let path = external_level(&external, Path::new("res/maps"));
+        assert_eq!(path.as_deref(), Some("res/maps/sample/Level_1.ldtkl"));
+    }
+
+    #[test]
+    fn bad_json_is_rejected() {
+        assert!(serde_json::from_str::<Project>(&"[".repeat(100_000)).is_err());
+        assert!(serde_json::from_str::<Project>(r#"{"levels": [{"identifier": 1}]}"#).is_err());
+        assert!(serde_json::from_str::<Project>(&SAMPLE[..SAMPLE.len() / 2]).is_err());
+    }
+}
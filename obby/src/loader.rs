@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use game::AssetError;
+use macroquad::experimental::coroutines::{Coroutine, start_coroutine};
+
+use crate::vfs::{self, Vfs};
+
+type Fetch = Coroutine<Result<Vec<u8>, String>>;
+
+/// Fetches files from a [`Vfs`] concurrently, each in its own coroutine, and
+/// keeps their bytes until they are taken.
+///
+/// Coroutines advance between frames, so the game keeps drawing while files
+/// arrive. Decoding textures and sounds is left to the caller.
+pub struct Loader {
+    vfs: Box<dyn Vfs>,
+    fetching: Vec<(String, Fetch)>,
+    files: HashMap<String, Result<Vec<u8>, AssetError>>,
+    requested: usize,
+    fetched: usize,
+}
+
+impl Default for Loader {
+    fn default() -> Self {
+        Self::new(vfs::default_vfs())
+    }
+}
+
+impl Loader {
+    pub fn new(vfs: Box<dyn Vfs>) -> Self {
+        Self {
+            vfs,
+            fetching: Vec::default(),
+            files: HashMap::default(),
+            requested: 0,
+            fetched: 0,
+        }
+    }
+
+    /// Starts fetching `path` unless it is already fetching or fetched.
+    pub fn request(&mut self, path: &str) {
+        if self.files.contains_key(path) || self.fetching.iter().any(|(p, _)| p == path) {
+            return;
+        }
+        self.requested += 1;
+        match self.vfs.fetch(path) {
+            Some(fetch) => self
+                .fetching
+                .push((path.to_string(), start_coroutine(fetch))),
+            None => {
+                let err = AssetError::new(path, "no such file");
+                self.files.insert(path.to_string(), Err(err));
+                self.fetched += 1;
+            }
+        }
+    }
+
+    /// Collects the files that finished fetching since the last call.
+    pub fn update(&mut self) {
+        let mut i = 0;
+        while i < self.fetching.len() {
+            if !self.fetching[i].1.is_done() {
+                i += 1;
+                continue;
+            }
+            let (path, fetch) = self.fetching.swap_remove(i);
+            let res = match fetch.retrieve() {
+                Some(res) => res.map_err(|err| AssetError::new(&path, err)),
+                None => Err(AssetError::new(&path, "loading was stopped")),
+            };
+            self.files.insert(path, res);
+            self.fetched += 1;
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.fetching.is_empty()
+    }
+
+    /// Share of the requested files that have been fetched, from 0 to 1.
+    pub fn progress(&self) -> f32 {
+        if self.requested == 0 {
+            return 1.0;
+        }
+        self.fetched as f32 / self.requested as f32
+    }
+
+    /// Returns the file at `path`, or `None` while it is still being fetched.
+    pub fn get(&self, path: &str) -> Option<Result<&[u8], AssetError>> {
+        let res = self.files.get(path)?;
+        Some(res.as_deref().map_err(Clone::clone))
+    }
+
+    /// Returns the file at `path` and forgets it, or `None` while it is still being fetched.
+    pub fn take(&mut self, path: &str) -> Option<Result<Vec<u8>, AssetError>> {
+        self.files.remove(path)
+    }
+
+    /// Returns the fetched file at `path`, an error if it failed or was never requested.
+    pub fn bytes(&self, path: &str) -> Result<&[u8], AssetError> {
+        self.get(path)
+            .unwrap_or_else(|| Err(AssetError::new(path, "was not loaded")))
+    }
+
+    /// Like [`Loader::bytes`] for text files.
+    pub fn text(&self, path: &str) -> Result<&str, AssetError> {
+        let bytes = self.bytes(path)?;
+        std::str::from_utf8(bytes).map_err(|err| AssetError::new(path, err))
+    }
+}
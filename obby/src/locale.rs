@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+use game::Message;
+
+use crate::{loader::Loader, storage::Storage};
+
+const LOCALE_KEY: &str = "language.cfg";
+
+/// Language codes with a file in `res/locales`, the first is the fallback.
+pub const LANGUAGES: [&str; 2] = ["en", "da"];
+
+pub fn path(code: &str) -> String {
+    format!("res/locales/{code}.txt")
+}
+
+/// The strings of one language, read from `res/locales/<code>.txt`.
+pub struct Locale {
+    pub code: String,
+    strings: HashMap<String, String>,
+}
+
+impl Locale {
+    /// Parses `id = text` lines, `\n` in a text starts a new line.
+    ///
+    /// Texts may refer to the arguments of a message as `{0}`, `{1}` and so on.
+    pub fn parse(path: &str, code: &str, text: &str) -> Self {
+        let mut strings = HashMap::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once("=") {
+                Some((id, s)) if !id.trim().is_empty() => {
+                    strings.insert(id.trim().to_string(), s.trim().replace("\\n", "\n"));
+                }
+                _ => println!("{path}:{}: expected id = text", n + 1),
+            }
+        }
+        Self {
+            code: code.to_string(),
+            strings,
+        }
+    }
+}
+
+/// All languages and the one currently chosen, saved to `language.cfg`.
+///
+/// A message missing from the chosen language is looked up in the fallback
+/// language and shown as its id if it is missing there too.
+#[derive(Default)]
+pub struct Locales {
+    locales: Vec<Locale>,
+    current: usize,
+}
+
+impl Locales {
+    /// Parses the locale files fetched by `loader` and picks the saved language.
+    pub fn load(storage: &dyn Storage, loader: &Loader) -> Self {
+        let mut locales = Vec::default();
+        for code in LANGUAGES {
+            let path = path(code);
+            match loader.text(&path) {
+                Ok(text) => locales.push(Locale::parse(&path, code, text)),
+                Err(err) => println!("{err}"),
+            }
+        }
+        let saved = storage.load(LOCALE_KEY).unwrap_or_default();
+        let current = locales
+            .iter()
+            .position(|l| l.code == saved.trim())
+            .unwrap_or_default();
+        Self { locales, current }
+    }
+
+    pub fn save(&self, storage: &mut dyn Storage) {
+        if let Some(locale) = self.locales.get(self.current) {
+            storage.save(LOCALE_KEY, &locale.code);
+        }
+    }
+
+    /// Locales of `locales` with the first one chosen.
+    #[cfg(test)]
+    pub fn new(locales: Vec<Locale>) -> Self {
+        Self {
+            locales,
+            current: 0,
+        }
+    }
+
+    /// Switches to the next or previous language, wrapping around.
+    pub fn cycle(&mut self, dir: i32) {
+        let len = self.locales.len().max(1) as i32;
+        self.current = (self.current as i32 + dir).rem_euclid(len) as usize;
+    }
+
+    /// Returns the text of `id` in the current language.
+    pub fn get<'a>(&'a self, id: &'a str) -> &'a str {
+        let current = self.locales.get(self.current);
+        let fallback = self.locales.first();
+        current
+            .and_then(|l| l.strings.get(id))
+            .or_else(|| fallback.and_then(|l| l.strings.get(id)))
+            .map(String::as_str)
+            .unwrap_or(id)
+    }
+
+    /// Returns the text of `id` with `{0}`, `{1}`, ... replaced by `args`.
+    pub fn format(&self, id: &str, args: &[String]) -> String {
+        let mut s = self.get(id).to_string();
+        for (i, arg) in args.iter().enumerate() {
+            s = s.replace(&format!("{{{i}}}"), arg);
+        }
+        s
+    }
+
+    pub fn message(&self, message: &Message) -> String {
+        self.format(message.id, &message.args)
+    }
+}
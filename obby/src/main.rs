@@ -1,14 +1,41 @@
+mod animation;
 mod assets;
-use crate::assets::{Assets, Atlas};
-use game::{Context as _, Game};
+mod bindings;
+mod highscores;
+mod input;
+mod ldtk;
+mod loader;
+mod locale;
+mod menu;
+mod mixer;
+mod particles;
+mod records;
+mod render;
+mod sfx;
+mod storage;
+mod text;
+mod vfs;
+mod view;
+use crate::assets::{Assets, Atlas, ENTITY_ATLAS};
+use crate::bindings::Bindings;
+use crate::highscores::HighScores;
+use crate::locale::Locales;
+use crate::menu::{Menu, MenuEvent};
+use crate::mixer::{Bus, Mixer};
+use crate::particles::Particles;
+use crate::records::{BestTimes, Ghosts, Split};
+use crate::render::Align;
+use crate::storage::Storage;
+use crate::text::{Text, TextStyle};
+use crate::view::View;
+use game::{AssetError, Context as _, EventKind, Game, Mode};
 use gamepads::Gamepads;
 use macroquad::{
-    audio::{PlaySoundParams, load_sound, play_sound},
     miniquad::window::set_window_size,
     prelude::*,
-    rand::rand,
+    rand::{RandGenerator, rand},
 };
-use std::collections::HashMap;
+use std::rc::Rc;
 
 const CHARACTERS: u8 = 5;
 
@@ -16,34 +43,58 @@ pub struct Context {
     pub map_list: Vec<String>,
     pub assets: Assets,
     pub frame_time: f32,
-    pub d_pad: Vec2,
-    pub keys_down: HashMap<game::Keys, ()>,
-    pub keys_pressed: HashMap<game::Keys, ()>,
+    pub inputs: [game::Input; 2],
+    pub players: usize,
     pub background: Color,
+    pub storage: Box<dyn Storage>,
+    pub best_times: BestTimes,
+    pub split: Option<Split>,
+    pub ghosts: Ghosts,
+    pub high_scores: HighScores,
+    pub mixer: Mixer,
+    pub fullscreen: bool,
+    pub locales: Locales,
+    /// Assets that failed to load at startup, the first is shown instead of the title.
+    pub errors: Vec<AssetError>,
+    pub bindings: Bindings,
+    pub particles: Particles,
 }
 
 impl Context {
-    pub fn play_sound(&self, key: &str, looped: bool, volume: f32) {
-        let Some(sounds) = self.assets.sfx.get(key) else {
-            return;
-        };
-        let i = rand() % sounds.len() as u32;
-        let Some(sound) = sounds.get(i as usize) else {
-            return;
+    /// Plays the sound and particle effect named `name`, at `pos` if it happened in the world.
+    pub fn play_effect(&mut self, name: &str, pos: Option<Vec2>, listener: Vec2) {
+        let (gain, pan) = match pos {
+            Some(pos) => {
+                self.particles.emit(name, pos);
+                mixer::spatialize(listener, pos)
+            }
+            None => (1.0, 0.0),
         };
-        play_sound(
-            sound,
-            PlaySoundParams {
-                looped,
-                volume,
-            },
-        );
+        self.play_sound(name, false, gain, pan);
+    }
+
+    pub fn play_sound(&mut self, key: &str, looped: bool, volume: f32, pan: f32) {
+        let volume = volume * self.mixer.gain(Bus::Sfx);
+        if volume > 0.0 {
+            self.assets.sfx.play(key, looped, volume, pan, get_time());
+        }
     }
 }
 
 #[derive(Clone)]
 pub struct Map {
     pub tiled_map: tiled::Map,
+    /// Index in [`Assets::atlases`] of each of the map's tilesets.
+    pub atlases: Vec<usize>,
+}
+
+// Tiled's chunks of infinite maps are streamed as they are
+const _: () = assert!(tiled::ChunkData::WIDTH as i32 == game::CHUNK_SIZE);
+
+impl Map {
+    fn tile_layer(&self) -> Option<tiled::TileLayer<'_>> {
+        self.tiled_map.get_layer(0).and_then(|l| l.as_tile_layer())
+    }
 }
 
 impl game::Map for Map {
@@ -57,40 +108,81 @@ impl game::Map for Map {
 
     fn tile(&self, x: i32, y: i32) -> game::MapTile {
         let mut game_tile = game::MapTile::default();
-        let layer = self
-            .tiled_map
-            .get_layer(0)
-            .and_then(|l| l.as_tile_layer())
-            .and_then(|l| l.get_tile(x, y));
+        let layer = self.tile_layer().and_then(|l| l.get_tile(x, y));
         if let Some(tile) = layer {
             game_tile.variant = tile.id();
-            if let Some(tile_data) = tile.get_tile() {
-                if let Some(user_type) = &tile_data.user_type {
-                    for user_type in user_type.split_whitespace() {
-                        match user_type {
-                            "block" => game_tile.is_block = true,
-                            "player" => game_tile.is_player = true,
-                            "goal" => game_tile.is_goal = true,
-                            "foreground" => game_tile.is_foreground = true,
-                            "entity" => game_tile.is_entity = true,
-                            "coin" => game_tile.is_coin = true,
-                            "deadly" => game_tile.is_deadly = true,
-                            "cloud" => game_tile.is_cloud = true,
-                            _ => {}
-                        }
-                    }
+            game_tile.tileset = self
+                .atlases
+                .get(tile.tileset_index())
+                .copied()
+                .unwrap_or_default() as u32;
+            game_tile.flip_x = tile.flip_h;
+            game_tile.flip_y = tile.flip_v;
+            game_tile.flip_d = tile.flip_d;
+            if let Some(tile_data) = tile.get_tile()
+                && let Some(user_type) = &tile_data.user_type
+            {
+                for kind in user_type.split_whitespace() {
+                    game_tile.set_kind(kind);
                 }
             }
         }
         game_tile
     }
 
+    /// Infinite maps cover their chunks, which may lie at negative coordinates.
+    fn bounds(&self) -> game::Bounds {
+        let Some(tiled::TileLayer::Infinite(layer)) = self.tile_layer() else {
+            let max = (self.tiled_map.width as i32, self.tiled_map.height as i32);
+            return game::Bounds { min: (0, 0), max };
+        };
+        let (w, h) = (
+            tiled::ChunkData::WIDTH as i32,
+            tiled::ChunkData::HEIGHT as i32,
+        );
+        let mut chunks = layer.chunk_data().map(|(pos, _)| pos);
+        let Some(first) = chunks.next() else {
+            return game::Bounds::default();
+        };
+        let (min, max) = chunks.fold((first, first), |(min, max), pos| {
+            (
+                (min.0.min(pos.0), min.1.min(pos.1)),
+                (max.0.max(pos.0), max.1.max(pos.1)),
+            )
+        });
+        game::Bounds {
+            min: (min.0 * w, min.1 * h),
+            max: ((max.0 + 1) * w, (max.1 + 1) * h),
+        }
+    }
+
+    fn chunks(&self) -> Vec<(i32, i32)> {
+        match self.tile_layer() {
+            Some(tiled::TileLayer::Infinite(layer)) => {
+                layer.chunk_data().map(|(pos, _)| pos).collect()
+            }
+            _ => self.bounds().chunks().collect(),
+        }
+    }
+
     fn background(&self) -> (u8, u8, u8) {
         self.tiled_map
             .background_color
             .map(|bg| (bg.red, bg.green, bg.blue))
             .unwrap_or((0, 0, 0))
     }
+
+    fn music(&self) -> Option<String> {
+        match self.tiled_map.properties.get("music")? {
+            tiled::PropertyValue::StringValue(path) => Some(path.clone()),
+            // file properties are relative to the map
+            tiled::PropertyValue::FileValue(path) => {
+                let dir = self.tiled_map.source.parent()?;
+                Some(assets::normalize(&dir.join(path)))
+            }
+            _ => None,
+        }
+    }
 }
 
 impl game::Context for Context {
@@ -103,11 +195,19 @@ impl game::Context for Context {
     }
 
     fn is_key_down(&self, key: game::Keys) -> bool {
-        self.keys_down.contains_key(&key)
+        self.inputs.iter().any(|i| i.is_key_down(key))
     }
 
     fn d_pad(&self) -> Vec2 {
-        self.d_pad
+        self.inputs
+            .iter()
+            .map(|i| i.d_pad)
+            .find(|d_pad| *d_pad != Vec2::ZERO)
+            .unwrap_or_default()
+    }
+
+    fn input(&self, player: u32) -> &game::Input {
+        &self.inputs[player as usize % self.inputs.len()]
     }
 
     fn map_list(&self) -> &Vec<String> {
@@ -119,106 +219,330 @@ impl game::Context for Context {
     }
 
     fn is_key_pressed(&self, key: game::Keys) -> bool {
-        self.keys_pressed.contains_key(&key)
+        self.inputs.iter().any(|i| i.is_key_pressed(key))
     }
 
     fn is_any_key_pressed(&self) -> bool {
-        !self.keys_pressed.is_empty()
+        self.inputs.iter().any(|i| !i.keys_pressed.is_empty())
     }
-}
 
-impl Context {
-    pub async fn new() -> Self {
-        let tileset = assets::Atlas::new(
-            20,
-            20,
-            load_texture("res/imgs/tileset.png")
-                .await
-                .expect("failed to load tileset"),
-        );
+    fn ghost(&mut self, map_name: &str) -> Option<Rc<game::Ghost>> {
+        self.ghosts.get(map_name, self.storage.as_ref())
+    }
+}
 
-        let map_list: Vec<String> = load_string("res/maps.txt")
-            .await
-            .unwrap()
-            .lines()
-            .map(str::to_owned)
-            .collect();
-
-        let mut sfx = HashMap::default();
-
-        for line in load_string("res/sfx.csv")
-            .await
-            .expect("failed to load sfx.csv")
-            .lines()
-        {
-            let mut cols = line.split(",");
-            let key = cols.next().unwrap();
-            let mut sounds = Vec::default();
-            for path in cols {
-                let path = path.trim();
-                let sound = load_sound(path).await.expect("failed to load sound");
-                sounds.push(sound);
-            }
-            sfx.insert(key.to_string(), sounds);
+/// Files fetched before the title shows, the files they list follow once they arrive.
+const STARTUP_FILES: [&str; 5] = [
+    "res/maps.txt",
+    "res/sfx.csv",
+    "res/particles.csv",
+    "res/fonts/system.ttf",
+    "res/imgs/tileset.png",
+];
+
+impl Default for Context {
+    /// Creates the context and starts fetching the startup files, see [`AppState::Loading`].
+    fn default() -> Self {
+        let storage = storage::default_storage();
+        let best_times = BestTimes::load(storage.as_ref());
+        let high_scores = HighScores::load(storage.as_ref());
+        let bindings = Bindings::load(storage.as_ref());
+        let mixer = Mixer::load(storage.as_ref());
+        let mut assets = Assets::default();
+        for path in STARTUP_FILES {
+            assets.loader.request(path);
         }
-
-        let mut sound_coin = Vec::new();
-        for n in 1..=2 {
-            sound_coin.push(load_sound(&format!("res/sfx/coin{n}.wav")).await.unwrap());
+        assets.loader.request(assets::TILESET_TSX);
+        for code in locale::LANGUAGES {
+            assets.loader.request(&locale::path(code));
         }
         Context {
-            map_list,
-            assets: Assets {
-                maps: Default::default(),
-                maps_pending: Default::default(),
-                tileset,
-                sfx,
-            },
+            map_list: Vec::default(),
+            assets,
             frame_time: 0.0,
-            d_pad: Default::default(),
-            keys_down: Default::default(),
-            keys_pressed: Default::default(),
+            inputs: Default::default(),
+            players: 1,
             background: WHITE,
+            storage,
+            best_times,
+            split: None,
+            ghosts: Default::default(),
+            high_scores,
+            mixer,
+            fullscreen: false,
+            locales: Locales::default(),
+            errors: Vec::default(),
+            bindings,
+            particles: Particles::new(Vec::default(), RandGenerator::new()),
+        }
+    }
+}
+
+impl Context {
+    /// Requests the sound files listed in `sfx.csv` and the first map of `maps.txt`.
+    pub fn request_listed(&mut self) {
+        let loader = &mut self.assets.loader;
+        let sounds = loader.text("res/sfx.csv").map(sfx::Sfx::files);
+        for path in sounds.unwrap_or_default() {
+            loader.request(&path);
+        }
+        let first_map = loader
+            .text("res/maps.txt")
+            .ok()
+            .and_then(|text| text.lines().next().map(str::to_string));
+        if let Some(map) = first_map {
+            self.assets.load_map(&map);
         }
     }
+
+    /// Decodes the fetched startup files, errors are kept in `errors`.
+    pub async fn load_assets(&mut self) {
+        if let Err(error) = self.assets.load_entity_tileset() {
+            self.errors.push(error);
+        }
+        let loader = &self.assets.loader;
+
+        match loader.text("res/maps.txt") {
+            Ok(text) => self.map_list = text.lines().map(str::to_owned).collect(),
+            Err(error) => self.errors.push(error),
+        }
+        for error in self.errors.iter() {
+            println!("{error}");
+        }
+
+        let rng = RandGenerator::new();
+        rng.srand(rand() as u64);
+        self.particles = match loader.text("res/particles.csv") {
+            Ok(text) => Particles::parse("res/particles.csv", text, rng),
+            Err(err) => {
+                println!("{err}");
+                Particles::new(Vec::default(), rng)
+            }
+        };
+        self.locales = Locales::load(self.storage.as_ref(), loader);
+        self.assets.text = Text::load("res/fonts/system.ttf", loader);
+        self.assets.sfx = sfx::Sfx::load("res/sfx.csv", loader).await;
+    }
 }
 
 fn process_events(app_state: &mut AppState, game: &Game, ctx: &mut Context) {
+    // sounds are heard from the midpoint between the players, where the camera is
+    let listener = game.players_midpoint();
     for event in game.events.iter() {
-        match event {
-            game::Event::PickupCoin => {
-                ctx.play_sound("coin", false, 1.0);
+        let pos = event.pos;
+        match &event.kind {
+            EventKind::PickupCoin => {
+                ctx.play_effect("coin", pos, listener);
             }
-            game::Event::Won => {
-                ctx.play_sound("win", false, 1.0);
+            EventKind::Won => {
+                ctx.play_effect("win", pos, listener);
+                if game.mode == Mode::TimeTrial
+                    && let Some(map) = ctx.map_list.get(game.level_current as usize)
+                {
+                    let split = ctx.best_times.submit(map, game.level_elapsed_sec);
+                    ctx.best_times.save(ctx.storage.as_mut());
+                    if split.is_record() {
+                        let ghost = game.ghost_recording.clone();
+                        ctx.ghosts.save(map, ghost, ctx.storage.as_mut());
+                    }
+                    ctx.split = Some(split);
+                }
             }
-            game::Event::Died => {
-                ctx.play_sound("lost", false, 1.0);
+            EventKind::Died => {
+                ctx.play_effect("lost", pos, listener);
             }
-            game::Event::PickupExtraLife => {
-                ctx.play_sound("extra_life", false, 1.0);
+            EventKind::PickupExtraLife => {
+                ctx.play_effect("extra_life", pos, listener);
             }
-            game::Event::PlayerJump => {
-                ctx.play_sound("jump", false, 1.0);
+            EventKind::PlayerJump => {
+                ctx.play_effect("jump", pos, listener);
             }
-            game::Event::GameOver { score } => {
+            EventKind::CloudCrumble => {
+                ctx.play_effect("crumble", pos, listener);
+            }
+            EventKind::LevelStarted => {
+                ctx.split = None;
+                ctx.particles.clear();
+            }
+            EventKind::GameOver { score } => {
                 *app_state = AppState::GameOver {
                     score: *score as f32,
                     score_display: 0.0,
+                    menu: Menu::new(gameover_items(ctx, *score).len()),
+                };
+            }
+            EventKind::Error(error) => {
+                *app_state = AppState::Error {
+                    error: error.clone(),
                 };
             }
         }
     }
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, PartialEq)]
 pub enum AppState {
-    Title { elapsed: f32 },
+    /// Startup files are being fetched, `listed` once the files they list were requested too.
+    Loading {
+        listed: bool,
+    },
+    Title {
+        elapsed: f32,
+        menu: Menu,
+    },
     Game,
-    GameOver { score: f32, score_display: f32 },
-    CharacterSelection { selection: u8 },
+    GameOver {
+        score: f32,
+        score_display: f32,
+        menu: Menu,
+    },
+    CharacterSelection {
+        menus: [Menu; 2],
+        ready: [bool; 2],
+        mode: Mode,
+    },
+    EnterName {
+        score: u32,
+        name: [u8; 3],
+        cursor: u8,
+    },
+    HighScores {
+        elapsed: f32,
+        highlight: Option<usize>,
+    },
+    Pause {
+        menu: Menu,
+    },
+    Options {
+        menu: Menu,
+    },
+    Controls {
+        profile: u8,
+        menu: Menu,
+        waiting: bool,
+    },
+    /// An asset failed to load, shown until confirmed.
+    Error {
+        error: AssetError,
+    },
+}
+
+const PAUSE_ITEMS: [&str; 4] = ["resume", "restart_level", "options", "quit_to_title"];
+/// Master, music and sfx volume, mute, fullscreen, language, controls and back.
+const OPTIONS_ITEMS: usize = 8;
+const OPTIONS_LANGUAGE: usize = 5;
+const OPTIONS_CONTROLS: usize = 6;
+const VOLUME_STEPS: [f32; 5] = [0.0, 0.25, 0.5, 0.75, 1.0];
+/// Profile row, one row per action, deadzone, reset and back.
+const CONTROLS_ITEMS: usize = bindings::ACTIONS.len() + 4;
+const DEADZONE_STEPS: [f32; 5] = [0.05, 0.1, 0.2, 0.3, 0.4];
+
+/// Steps `value` to the next or previous entry of `steps`, wrapping around.
+fn step(steps: &[f32], value: f32, dir: i32) -> f32 {
+    let i = steps.iter().position(|v| *v >= value).unwrap_or_default() as i32;
+    steps[(i + dir).rem_euclid(steps.len() as i32) as usize]
+}
+
+/// Looks up each of `ids` in the current language.
+fn localize(locales: &Locales, ids: &[&str]) -> Vec<String> {
+    ids.iter().map(|id| locales.get(id).to_string()).collect()
+}
+
+/// The modes followed by high scores.
+fn title_items(locales: &Locales) -> Vec<String> {
+    let mut items: Vec<&str> = MODES.iter().map(|(_, id)| *id).collect();
+    items.push("high_scores");
+    localize(locales, &items)
+}
+
+fn gameover_items(ctx: &Context, score: u32) -> Vec<String> {
+    if ctx.high_scores.qualifies(score) {
+        localize(&ctx.locales, &["enter_name"])
+    } else {
+        localize(&ctx.locales, &["play_again", "quit_to_title"])
+    }
+}
+
+fn controls_items(ctx: &Context, profile: u8, selection: usize, waiting: bool) -> Vec<String> {
+    let locales = &ctx.locales;
+    let map = &ctx.bindings.profiles[profile as usize];
+    let id = format!("profile.{}", bindings::PROFILES[profile as usize]);
+    let mut items = vec![locales.format("profile", &[locales.get(&id).to_string()])];
+    for (i, (action, name)) in bindings::ACTIONS.iter().enumerate() {
+        let bound = if waiting && selection == i + 1 {
+            locales.get("press_a_key").to_string()
+        } else {
+            map.get(*action)
+                .iter()
+                .map(|b| match b {
+                    bindings::Binding::Key(key) => format!("{key:?}").to_uppercase(),
+                    bindings::Binding::Button(button) => {
+                        locales.format("pad", &[format!("{button:?}").to_uppercase()])
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let name = locales.get(&format!("action.{name}")).to_string();
+        items.push(locales.format("binding", &[name, bound]));
+    }
+    let deadzone = (ctx.bindings.deadzone * 100.0).round();
+    items.push(locales.format("stick_deadzone", &[deadzone.to_string()]));
+    items.push(locales.get("reset_defaults").to_string());
+    items.push(locales.get("back").to_string());
+    items
+}
+
+fn options_items(ctx: &Context) -> Vec<String> {
+    let locales = &ctx.locales;
+    let volume = |id: &str, bus: Bus| {
+        let percent = (ctx.mixer.volume(bus) * 100.0).round();
+        locales.format(id, &[percent.to_string()])
+    };
+    let on_off = |id: &str, on: bool| {
+        let value = locales.get(if on { "on" } else { "off" });
+        locales.format(id, &[value.to_string()])
+    };
+    vec![
+        volume("volume", Bus::Master),
+        volume("music", Bus::Music),
+        volume("sound_effects", Bus::Sfx),
+        on_off("mute", ctx.mixer.muted),
+        on_off("fullscreen", ctx.fullscreen),
+        locales.format("language", &[locales.get("language_name").to_string()]),
+        locales.get("controls").to_string(),
+        locales.get("back").to_string(),
+    ]
 }
 
+/// Seconds of idling before the title and high score screens swap.
+const ATTRACT_SEC: f32 = 10.0;
+
+impl AppState {
+    pub fn title() -> Self {
+        AppState::Title {
+            elapsed: 0.0,
+            menu: Menu::new(MODES.len() + 1),
+        }
+    }
+
+    pub fn character_selection(mode: Mode) -> Self {
+        let menu = Menu::horizontal(CHARACTERS as usize);
+        AppState::CharacterSelection {
+            menus: [menu, menu.with_focus(1)],
+            ready: [false; 2],
+            mode,
+        }
+    }
+}
+
+const MODES: [(Mode, &str); 4] = [
+    (Mode::Story, "mode.story"),
+    (Mode::TimeTrial, "mode.time_trial"),
+    (Mode::Coop, "mode.coop"),
+    (Mode::Versus, "mode.versus"),
+];
+
 #[macroquad::main("HØRUP'S OBBY")]
 async fn main() {
     let scale = 2;
@@ -236,25 +560,22 @@ async fn main() {
         ..Default::default()
     };
 
-    let mut ctx = Context::new().await;
-
+    let mut ctx = Context::default();
     let mut game = Game::default();
-    game.init(&mut ctx);
 
     let target_width = target_width as f32;
     let target_height = target_height as f32;
-    let mut camera_offset_x_px = 0.0;
+    let mut camera_offset_px = Vec2::ZERO;
 
     let mut gamepads = Gamepads::new();
-    let mut full_screen = true;
 
-    let mut app_state = AppState::Title { elapsed: 0.0 };
+    let mut app_state = AppState::Loading { listed: false };
     let mut secs = 0.0;
     loop {
         let flashing = (secs * 3.0) as i32 % 2 == 0;
         if is_key_down(KeyCode::LeftAlt) && is_key_pressed(KeyCode::Enter) {
-            set_fullscreen(full_screen);
-            full_screen = !full_screen;
+            ctx.fullscreen = !ctx.fullscreen;
+            set_fullscreen(ctx.fullscreen);
         }
         if is_key_pressed(KeyCode::F1) {
             if app_state != AppState::Game {
@@ -264,162 +585,462 @@ async fn main() {
             }
         }
         if is_key_pressed(KeyCode::F2) {
-            app_state = AppState::CharacterSelection { selection: 0 };
+            app_state = AppState::character_selection(Mode::Story);
         }
         set_camera(&camera);
         ctx.frame_time = get_frame_time().min(0.1);
-        ctx.keys_down.clear();
-        ctx.keys_pressed.clear();
-        // keyboard input
-        if is_key_down(KeyCode::Space) || is_key_down(KeyCode::Up) {
-            ctx.keys_down.insert(game::Keys::Space, ());
-        }
-        if is_key_pressed(KeyCode::Space) || is_key_pressed(KeyCode::Up) {
-            ctx.keys_pressed.insert(game::Keys::Space, ());
-        }
-
-        let mut d_pad = Vec2::default();
-        if is_key_down(KeyCode::A) || is_key_down(KeyCode::Left) {
-            d_pad.x = -1.0;
-            ctx.keys_down.insert(game::Keys::Left, ());
-        } else if is_key_down(KeyCode::D) || is_key_down(KeyCode::Right) {
-            d_pad.x = 1.0;
-            ctx.keys_down.insert(game::Keys::Right, ());
-        }
-        if is_key_pressed(KeyCode::A) || is_key_pressed(KeyCode::Left) {
-            d_pad.x = -1.0;
-            ctx.keys_pressed.insert(game::Keys::Left, ());
-        } else if is_key_pressed(KeyCode::D) || is_key_pressed(KeyCode::Right) {
-            d_pad.x = 1.0;
-            ctx.keys_pressed.insert(game::Keys::Right, ());
-        }
-
-        // gamepad input
+        // input
         gamepads.poll();
+        input::poll(&mut ctx.inputs, ctx.players, &gamepads, &ctx.bindings);
 
-        for gamepad in gamepads.all() {
-            for button in gamepad.all_currently_pressed() {
-                //println!("Pressed button: {:?}", button);
-                match button {
-                    gamepads::Button::DPadLeft => {
-                        d_pad.x = -1.0;
-                        ctx.keys_down.insert(game::Keys::Left, ());
-                    }
-                    gamepads::Button::DPadRight => {
-                        d_pad.x = 1.0;
-                        ctx.keys_down.insert(game::Keys::Right, ());
+        // update
+        match &mut app_state {
+            AppState::Loading { listed } => {
+                if ctx.assets.loader.is_done() {
+                    if !*listed {
+                        ctx.request_listed();
+                        *listed = true;
+                    } else {
+                        ctx.load_assets().await;
+                        game.init(&mut ctx);
+                        app_state = match ctx.errors.first() {
+                            Some(error) => AppState::Error {
+                                error: error.clone(),
+                            },
+                            None => AppState::title(),
+                        };
                     }
-                    gamepads::Button::ActionRight => {
-                        ctx.keys_down.insert(game::Keys::Space, ());
+                }
+            }
+            AppState::Title { elapsed, menu } => {
+                *elapsed += ctx.dt();
+                if *elapsed > 1.0 {
+                    let focus = menu.focus;
+                    match menu.update(|k| ctx.is_key_pressed(k)) {
+                        Some(MenuEvent::Confirm(i)) => match MODES.get(i) {
+                            Some((mode, _)) => {
+                                ctx.players = mode.players();
+                                app_state = AppState::character_selection(*mode);
+                            }
+                            None => {
+                                app_state = AppState::HighScores {
+                                    elapsed: 0.0,
+                                    highlight: None,
+                                }
+                            }
+                        },
+                        _ if menu.focus != focus => *elapsed = 1.0,
+                        _ if *elapsed > ATTRACT_SEC => {
+                            app_state = AppState::HighScores {
+                                elapsed: 0.0,
+                                highlight: None,
+                            };
+                        }
+                        _ => {}
                     }
-                    gamepads::Button::ActionDown => {
-                        ctx.keys_down.insert(game::Keys::Space, ());
+                }
+            }
+            AppState::Game => {
+                game.update(&mut ctx);
+                ctx.particles.update(ctx.dt());
+                // fetch the next map while this one is played, so its intro does not wait
+                if let Some(next) = ctx.map_list.get(game.level_current as usize + 1) {
+                    ctx.assets.load_map(next);
+                }
+                if is_key_pressed(KeyCode::F2) {
+                    game.events.push(EventKind::GameOver { score: 1337 }.into());
+                }
+                process_events(&mut app_state, &game, &mut ctx);
+                game.events.clear();
+                if ctx.is_key_pressed(game::Keys::Pause) {
+                    app_state = AppState::Pause {
+                        menu: Menu::new(PAUSE_ITEMS.len()),
+                    };
+                }
+            }
+            AppState::Pause { menu } => {
+                if ctx.is_key_pressed(game::Keys::Pause) {
+                    app_state = AppState::Game;
+                } else {
+                    match menu.update(|k| ctx.is_key_pressed(k)) {
+                        Some(MenuEvent::Back) | Some(MenuEvent::Confirm(0)) => {
+                            app_state = AppState::Game
+                        }
+                        Some(MenuEvent::Confirm(1)) => {
+                            game.restart(&mut ctx, false);
+                            app_state = AppState::Game;
+                        }
+                        Some(MenuEvent::Confirm(2)) => {
+                            app_state = AppState::Options {
+                                menu: Menu::new(OPTIONS_ITEMS),
+                            }
+                        }
+                        Some(MenuEvent::Confirm(_)) => {
+                            ctx.players = 1;
+                            app_state = AppState::title();
+                        }
+                        _ => {}
                     }
-                    _ => {}
                 }
             }
-
-            for button in gamepad.all_just_pressed() {
-                match button {
-                    gamepads::Button::ActionRight => {
-                        ctx.keys_pressed.insert(game::Keys::Space, ());
+            AppState::Options { menu } => {
+                let back = AppState::Pause {
+                    menu: Menu::new(PAUSE_ITEMS.len()).with_focus(2),
+                };
+                let event = menu.update(|k| ctx.is_key_pressed(k));
+                match event {
+                    Some(MenuEvent::Confirm(i)) | Some(MenuEvent::Adjust(i, _)) if i < 3 => {
+                        let dir = match event {
+                            Some(MenuEvent::Adjust(_, dir)) => dir,
+                            _ => 1,
+                        };
+                        let bus = Bus::ALL[i];
+                        let volume = step(&VOLUME_STEPS, ctx.mixer.volume(bus), dir);
+                        ctx.mixer.set_volume(bus, volume);
+                        ctx.mixer.save(ctx.storage.as_mut());
                     }
-                    gamepads::Button::ActionDown => {
-                        ctx.keys_pressed.insert(game::Keys::Space, ());
+                    Some(MenuEvent::Confirm(3)) | Some(MenuEvent::Adjust(3, _)) => {
+                        ctx.mixer.muted = !ctx.mixer.muted;
+                        ctx.mixer.save(ctx.storage.as_mut());
                     }
-                    gamepads::Button::DPadLeft => {
-                        ctx.keys_pressed.insert(game::Keys::Left, ());
+                    Some(MenuEvent::Confirm(4)) | Some(MenuEvent::Adjust(4, _)) => {
+                        ctx.fullscreen = !ctx.fullscreen;
+                        set_fullscreen(ctx.fullscreen);
                     }
-                    gamepads::Button::DPadRight => {
-                        ctx.keys_pressed.insert(game::Keys::Right, ());
+                    Some(MenuEvent::Confirm(OPTIONS_LANGUAGE)) => {
+                        ctx.locales.cycle(1);
+                        ctx.locales.save(ctx.storage.as_mut());
                     }
+                    Some(MenuEvent::Adjust(OPTIONS_LANGUAGE, dir)) => {
+                        ctx.locales.cycle(dir);
+                        ctx.locales.save(ctx.storage.as_mut());
+                    }
+                    Some(MenuEvent::Confirm(OPTIONS_CONTROLS)) => {
+                        app_state = AppState::Controls {
+                            profile: 0,
+                            menu: Menu::new(CONTROLS_ITEMS),
+                            waiting: false,
+                        }
+                    }
+                    Some(MenuEvent::Confirm(_)) | Some(MenuEvent::Back) => app_state = back,
                     _ => {}
                 }
             }
-        }
-
-        ctx.d_pad = d_pad.normalize_or_zero();
-
-        // update
-        match &mut app_state {
-            AppState::Title { elapsed } => {
-                *elapsed += ctx.dt();
-                if *elapsed > 1.0
-                    && ctx.is_any_key_pressed() {
-                        app_state = AppState::CharacterSelection { selection: 0 };
+            AppState::Controls {
+                profile,
+                menu,
+                waiting,
+            } => {
+                let action = bindings::ACTIONS
+                    .get(menu.focus.wrapping_sub(1))
+                    .map(|(action, _)| *action);
+                let map = &mut ctx.bindings.profiles[*profile as usize];
+                let profiles = bindings::PROFILES.len() as i32;
+                let deadzone_row = CONTROLS_ITEMS - 3;
+                let reset_row = CONTROLS_ITEMS - 2;
+                if *waiting {
+                    if is_key_pressed(KeyCode::Escape) {
+                        *waiting = false;
+                    } else if let Some(action) = action
+                        && let Some(binding) = input::last_binding_pressed(&gamepads)
+                    {
+                        if binding == bindings::Binding::Key(KeyCode::Backspace) {
+                            map.clear(action);
+                        } else {
+                            map.bind(action, binding);
+                        }
+                        *waiting = false;
+                        ctx.bindings.save(ctx.storage.as_mut());
+                    }
+                } else {
+                    match menu.update(|k| ctx.is_key_pressed(k)) {
+                        Some(MenuEvent::Confirm(0)) => {
+                            *profile = (*profile as i32 + 1).rem_euclid(profiles) as u8
+                        }
+                        Some(MenuEvent::Adjust(0, dir)) => {
+                            *profile = (*profile as i32 + dir).rem_euclid(profiles) as u8
+                        }
+                        Some(MenuEvent::Confirm(_)) if action.is_some() => *waiting = true,
+                        Some(MenuEvent::Confirm(i)) if i == deadzone_row => {
+                            let deadzone = &mut ctx.bindings.deadzone;
+                            *deadzone = step(&DEADZONE_STEPS, *deadzone, 1);
+                            ctx.bindings.save(ctx.storage.as_mut());
+                        }
+                        Some(MenuEvent::Adjust(i, dir)) if i == deadzone_row => {
+                            let deadzone = &mut ctx.bindings.deadzone;
+                            *deadzone = step(&DEADZONE_STEPS, *deadzone, dir);
+                            ctx.bindings.save(ctx.storage.as_mut());
+                        }
+                        Some(MenuEvent::Confirm(i)) if i == reset_row => {
+                            ctx.bindings = Bindings::default();
+                            ctx.bindings.save(ctx.storage.as_mut());
+                        }
+                        Some(MenuEvent::Confirm(_)) | Some(MenuEvent::Back) => {
+                            app_state = AppState::Options {
+                                menu: Menu::new(OPTIONS_ITEMS).with_focus(OPTIONS_CONTROLS),
+                            };
+                        }
+                        _ => {}
                     }
-            }
-            AppState::Game => {
-                game.update(&mut ctx);
-                if is_key_pressed(KeyCode::F2) {
-                    game.events.push(game::Event::GameOver { score: 1337 });
                 }
-                process_events(&mut app_state, &game, &mut ctx);
-                game.events.clear();
             }
             AppState::GameOver {
                 score,
                 score_display,
+                menu,
             } => {
                 *score_display += ctx.dt() * *score / 4.0;
                 if score_display >= score {
                     *score_display = *score;
-                    if ctx.is_any_key_pressed() {
-                        app_state = AppState::Title { elapsed: 0.0 };
+                    let score = *score as u32;
+                    if let Some(MenuEvent::Confirm(i)) = menu.update(|k| ctx.is_key_pressed(k)) {
+                        ctx.players = 1;
+                        app_state = if ctx.high_scores.qualifies(score) {
+                            AppState::EnterName {
+                                score,
+                                name: *b"AAA",
+                                cursor: 0,
+                            }
+                        } else if i == 0 {
+                            AppState::character_selection(game.mode)
+                        } else {
+                            AppState::title()
+                        };
                     }
                 }
             }
-            AppState::CharacterSelection { selection } => {
-                if ctx.is_key_pressed(game::Keys::Space) {
+            AppState::EnterName {
+                score,
+                name,
+                cursor,
+            } => {
+                let letter = &mut name[*cursor as usize];
+                let mut typed = None;
+                while let Some(c) = get_char_pressed() {
+                    if c.is_ascii_alphabetic() {
+                        typed = Some(c.to_ascii_uppercase() as u8);
+                    }
+                }
+                if let Some(c) = typed {
+                    *letter = c;
+                    *cursor += 1;
+                } else if ctx.is_key_pressed(game::Keys::Back)
+                    || ctx.is_key_pressed(game::Keys::Left)
+                {
+                    *cursor = cursor.saturating_sub(1);
+                } else if ctx.is_key_pressed(game::Keys::Up) {
+                    *letter = if *letter >= b'Z' { b'A' } else { *letter + 1 };
+                } else if ctx.is_key_pressed(game::Keys::Down) {
+                    *letter = if *letter <= b'A' { b'Z' } else { *letter - 1 };
+                } else if ctx.is_key_pressed(game::Keys::Confirm)
+                    || ctx.is_key_pressed(game::Keys::Right)
+                {
+                    *cursor += 1;
+                }
+                if *cursor as usize >= name.len() {
+                    let name = String::from_utf8_lossy(name).to_string();
+                    let rank = ctx.high_scores.insert(&name, *score);
+                    ctx.high_scores.save(ctx.storage.as_mut());
+                    app_state = AppState::HighScores {
+                        elapsed: 0.0,
+                        highlight: Some(rank),
+                    };
+                }
+            }
+            AppState::HighScores { elapsed, .. } => {
+                *elapsed += ctx.dt();
+                let pressed =
+                    ctx.is_key_pressed(game::Keys::Confirm) || ctx.is_key_pressed(game::Keys::Back);
+                if (*elapsed > 1.0 && pressed) || *elapsed > ATTRACT_SEC {
+                    app_state = AppState::title();
+                }
+            }
+            AppState::CharacterSelection { menus, ready, mode } => {
+                let players = mode.players();
+                ctx.players = players;
+                let mut back = false;
+                for player in 0..players {
+                    let input = ctx.input(player as u32);
+                    let event = if ready[player] {
+                        input
+                            .is_key_pressed(game::Keys::Back)
+                            .then_some(MenuEvent::Back)
+                    } else {
+                        menus[player].update(|k| input.is_key_pressed(k))
+                    };
+                    match event {
+                        Some(MenuEvent::Confirm(_)) => ready[player] = true,
+                        Some(MenuEvent::Back) if ready[player] => ready[player] = false,
+                        Some(MenuEvent::Back) => back = true,
+                        _ => {}
+                    }
+                }
+                if back {
+                    ctx.players = 1;
+                    app_state = AppState::title();
+                } else if ready[..players].iter().all(|r| *r) {
                     game = Game::default();
-                    game.skin_chosen = *selection as u32;
+                    game.skins_chosen = menus[..players].iter().map(|m| m.focus as u32).collect();
+                    game.mode = *mode;
                     game.init(&mut ctx);
                     app_state = AppState::Game;
-                } else {
-                    if ctx.is_key_pressed(game::Keys::Right) {
-                        *selection = selection.wrapping_add(1);
-                    } else if ctx.is_key_pressed(game::Keys::Left) {
-                        *selection = selection.wrapping_sub(1);
-                    }
-                    if *selection >= CHARACTERS {
-                        *selection = 0;
-                    }
+                }
+            }
+            AppState::Error { .. } => {
+                if ctx.is_key_pressed(game::Keys::Confirm) || ctx.is_key_pressed(game::Keys::Back) {
+                    app_state = AppState::title();
                 }
             }
         }
 
         // draw
         match &app_state {
-            AppState::Title { elapsed } => {
-                let flashing = if *elapsed > 1.0 { flashing } else { false };
-                draw_title(target_width, target_height, flashing);
+            AppState::Loading { listed } => {
+                // the files listed by the first ones fill the second half of the bar
+                let progress = ctx.assets.loader.progress();
+                let progress = if *listed {
+                    0.5 + progress / 2.0
+                } else {
+                    progress / 2.0
+                };
+                draw_loading(target_width, target_height, progress);
+            }
+            AppState::Title { menu, .. } => {
+                draw_title(
+                    target_width,
+                    target_height,
+                    menu,
+                    &ctx.locales,
+                    &ctx.assets.text,
+                );
             }
             AppState::Game => {
                 draw_game(
                     &game,
-                    &mut camera_offset_x_px,
+                    &mut camera_offset_px,
                     target_width,
                     target_height,
-                    &mut ctx,
+                    &ctx,
                 );
             }
             AppState::GameOver {
                 score,
                 score_display,
+                menu,
             } => {
-                let flashing = if score == score_display {
-                    flashing
+                let items = if score == score_display {
+                    gameover_items(&ctx, *score as u32)
                 } else {
-                    false
+                    Vec::default()
                 };
-                draw_gameover(target_width, target_height, flashing, *score_display as u32);
+                draw_gameover(
+                    target_width,
+                    target_height,
+                    *score_display as u32,
+                    menu,
+                    &items,
+                    &ctx.locales,
+                    &ctx.assets.text,
+                );
+            }
+            AppState::EnterName {
+                score,
+                name,
+                cursor,
+            } => {
+                draw_enter_name(
+                    target_width,
+                    target_height,
+                    flashing,
+                    *score,
+                    name,
+                    *cursor,
+                    &ctx.locales,
+                    &ctx.assets.text,
+                );
+            }
+            AppState::HighScores { highlight, .. } => {
+                draw_high_scores(
+                    target_width,
+                    target_height,
+                    &ctx.high_scores,
+                    *highlight,
+                    &ctx.locales,
+                    &ctx.assets.text,
+                );
+            }
+            AppState::Pause { menu } => {
+                draw_game(
+                    &game,
+                    &mut camera_offset_px,
+                    target_width,
+                    target_height,
+                    &ctx,
+                );
+                let items = localize(&ctx.locales, &PAUSE_ITEMS);
+                draw_menu(
+                    target_width,
+                    target_height,
+                    ctx.locales.get("paused"),
+                    &items,
+                    menu,
+                    &ctx.assets.text,
+                );
             }
-            AppState::CharacterSelection { selection } => {
+            AppState::Options { menu } => {
+                draw_game(
+                    &game,
+                    &mut camera_offset_px,
+                    target_width,
+                    target_height,
+                    &ctx,
+                );
+                let items = options_items(&ctx);
+                draw_menu(
+                    target_width,
+                    target_height,
+                    ctx.locales.get("options"),
+                    &items,
+                    menu,
+                    &ctx.assets.text,
+                );
+            }
+            AppState::Controls {
+                profile,
+                menu,
+                waiting,
+            } => {
+                clear_background(BLACK);
+                let items = controls_items(&ctx, *profile, menu.focus, *waiting);
+                draw_menu(
+                    target_width,
+                    target_height,
+                    ctx.locales.get("controls"),
+                    &items,
+                    menu,
+                    &ctx.assets.text,
+                );
+            }
+            AppState::CharacterSelection { menus, ready, mode } => {
+                let players = mode.players();
+                let selection: Vec<u8> = menus[..players].iter().map(|m| m.focus as u8).collect();
                 draw_character_selection(
                     target_width,
                     target_height,
-                    selection,
-                    &ctx.assets.tileset,
+                    &selection,
+                    &ready[..players],
+                    &ctx.assets.atlases[ENTITY_ATLAS],
+                    &ctx.locales,
+                    &ctx.assets.text,
+                );
+            }
+            AppState::Error { error } => {
+                draw_error(
+                    target_width,
+                    target_height,
+                    error,
+                    &ctx.locales,
+                    &ctx.assets.text,
                 );
             }
         }
@@ -427,31 +1048,51 @@ async fn main() {
         set_default_camera();
         blit_render_target(&render_target.texture, target_width, target_height);
 
-        ctx.assets.load_pending().await;
+        // music follows the map being played, also while paused
+        let music = match app_state {
+            AppState::Game
+            | AppState::Pause { .. }
+            | AppState::Options { .. }
+            | AppState::Controls { .. } => game.map_current.as_ref().and_then(|m| m.music()),
+            _ => None,
+        };
+        ctx.mixer.play_music(music.as_deref());
+        ctx.mixer.update(get_frame_time());
+
+        ctx.assets.load_pending();
+        ctx.mixer.load_pending(&mut ctx.assets.loader).await;
         next_frame().await;
         secs += get_frame_time();
     }
 }
 
 const SKIN_INDEX: [f32; 5] = [120.0, 121.0, 122.0, 123.0, 124.0];
+const PLAYER_COLORS: [Color; 2] = [SKYBLUE, ORANGE];
 
 fn draw_character_selection(
     target_width: f32,
     target_height: f32,
-    selection: &u8,
+    selection: &[u8],
+    ready: &[bool],
     tileset: &Atlas,
+    locales: &Locales,
+    text: &Text,
 ) {
     clear_background(BLACK);
     let m = target_width / (CHARACTERS + 1) as f32;
 
     let font_size = target_height / 8.0;
-    let s = "CHOOSE CHARACTER".to_string();
-    let measure = measure_text(&s, None, font_size as u16, 1.0);
-    let x = target_width / 2.0 - measure.width / 2.0;
-    let y = target_height / 4.0 - measure.height;
-    draw_text(&s, x, y, font_size, WHITE);
-
-    let texts = ["WILLIAM", "VIKTOR", "SIGGA", "LOUISE", "SØREN"];
+    let s = locales.get("choose_character");
+    let y = target_height / 4.0 - text.measure(s, font_size).height;
+    text.draw(s, target_width / 2.0, y, heading(font_size));
+
+    let texts = [
+        "character.william",
+        "character.viktor",
+        "character.sigga",
+        "character.louise",
+        "character.soren",
+    ];
 
     let draw_character = |col: u8, highlighted: bool| {
         let color = if highlighted { WHITE } else { DARKGRAY };
@@ -470,278 +1111,243 @@ fn draw_character_selection(
             false,
             false,
         );
-        let text = texts[col as usize];
+        let s = locales.get(texts[col as usize]);
 
         let font_size = target_height / 16.0;
-        let measure = measure_text(text, None, font_size as u16, 1.0);
-        let x = x + size / 2.0 - measure.width / 2.0;
-        let y = y + size + measure.height * 2.0;
-        draw_text(text, x, y, font_size, color);
+        let y = y + size + text.measure(s, font_size).height * 2.0;
+        text.draw(s, x + size / 2.0, y, centered(font_size, color));
     };
 
     for i in 0..CHARACTERS {
-        draw_character(i, *selection == i);
+        draw_character(i, selection.contains(&i));
+    }
+
+    // draw player tags above the chosen characters
+    if selection.len() > 1 {
+        let font_size = target_height / 16.0;
+        for (player, col) in selection.iter().enumerate() {
+            let id = if ready[player] {
+                "player_ready"
+            } else {
+                "player_tag"
+            };
+            let s = locales.format(id, &[(player + 1).to_string()]);
+            let color = PLAYER_COLORS[player % PLAYER_COLORS.len()];
+            let height = text.measure(&s, font_size).height;
+            let x = m * *col as f32 + m;
+            let y = target_height / 2.0 - m / 2.0 - height * (player as f32 * 1.5 + 0.5);
+            text.draw(&s, x, y, centered(font_size, color));
+        }
     }
 }
 
-fn draw_gameover(target_width: f32, target_height: f32, flashing: bool, score: u32) {
-    clear_background(BLACK);
-    let font_size = target_height / 8.0;
-    let s = "GAME OVER";
-    let measure = measure_text(s, None, font_size as u16, 1.0);
-    let x = target_width / 2.0 - measure.width / 2.0;
-    let y = target_height / 2.0 - measure.height;
-    draw_text(s, x, y, font_size, WHITE);
+/// Style for centered lines of text.
+fn centered(font_size: f32, color: Color) -> TextStyle {
+    TextStyle::new(font_size, color).with_align(Align::Center)
+}
 
-    let font_size = target_height / 16.0;
-    let s = format!("FINAL SCORE:{score}");
-    let measure = measure_text(&s, None, font_size as u16, 1.0);
-    let x = target_width / 2.0 - measure.width / 2.0;
-    let y = target_height / 2.0 - measure.height + font_size / 2.0;
-    draw_text(&s, x, y, font_size, WHITE);
+/// Style for the big centered line at the top of a screen.
+fn heading(font_size: f32) -> TextStyle {
+    centered(font_size, WHITE).with_shadow(DARKGRAY)
+}
 
-    if flashing {
-        let font_size = target_height / 16.0;
-        let s = "PRESS ANY BUTTON TO CONTINUE";
-        let measure = measure_text(s, None, font_size as u16, 1.0);
-        let x = target_width / 2.0 - measure.width / 2.0;
-        let y = target_height / 2.0 - measure.height + font_size * 3.0;
-        draw_text(s, x, y, font_size, RED);
-    }
+fn draw_menu(
+    target_width: f32,
+    target_height: f32,
+    title: &str,
+    items: &[String],
+    menu: &Menu,
+    text: &Text,
+) {
+    let transparent_color = Color::from_rgba(0, 0, 0, 255 / 4 * 3);
+    draw_rectangle(0.0, 0.0, target_width, target_height, transparent_color);
+
+    let font_size = target_height / 8.0;
+    let y = target_height / 4.0;
+    text.draw(title, target_width / 2.0, y, heading(font_size));
+
+    // shrink long lists so they fit below the title
+    let font_size = (target_height / 16.0).min(target_height * 0.6 / (items.len() as f32 * 1.5));
+    let y = target_height / 4.0 + target_height / 8.0;
+    menu.draw(items, target_width, y, font_size, text);
 }
 
-fn draw_title(target_width: f32, target_height: f32, flashing: bool) {
+#[allow(clippy::too_many_arguments)]
+fn draw_enter_name(
+    target_width: f32,
+    target_height: f32,
+    flashing: bool,
+    score: u32,
+    name: &[u8],
+    cursor: u8,
+    locales: &Locales,
+    text: &Text,
+) {
     clear_background(BLACK);
+    let center = target_width / 2.0;
     let font_size = target_height / 8.0;
-    let s = "HØRUP'S OBBY";
-    let measure = measure_text(s, None, font_size as u16, 1.0);
-    let x = target_width / 2.0 - measure.width / 2.0;
-    let y = target_height / 2.0 - measure.height;
-    draw_text(s, x, y, font_size, WHITE);
+    let y = target_height / 4.0;
+    let s = locales.get("new_high_score");
+    text.draw(s, center, y, heading(font_size));
 
     let font_size = target_height / 16.0;
-    let s = "A GAME ABOUT NOT DYING";
-    let measure = measure_text(s, None, font_size as u16, 1.0);
-    let x = target_width / 2.0 - measure.width / 2.0;
-    let y = target_height / 2.0 - measure.height + font_size / 2.0;
-    draw_text(s, x, y, font_size, WHITE);
-
-    if flashing {
-        let font_size = target_height / 16.0;
-        let s = "PRESS ANY BUTTON TO CONTINUE";
-        let measure = measure_text(s, None, font_size as u16, 1.0);
-        let x = target_width / 2.0 - measure.width / 2.0;
-        let y = target_height / 2.0 - measure.height + font_size * 3.0;
-        draw_text(s, x, y, font_size, RED);
+    let y = target_height / 4.0 + font_size * 1.5;
+    let s = locales.format("score_entry", &[score.to_string()]);
+    text.draw(&s, center, y, centered(font_size, WHITE));
+
+    // draw the letters with the one being edited flashing
+    let font_size = target_height / 6.0;
+    let letter_width = font_size;
+    for (i, letter) in name.iter().enumerate() {
+        let s = (*letter as char).to_string();
+        let color = if i as u8 == cursor {
+            if flashing { RED } else { WHITE }
+        } else {
+            DARKGRAY
+        };
+        let x = center + (i as f32 - 1.0) * letter_width;
+        let y = target_height / 2.0 + text.measure(&s, font_size).height / 2.0;
+        text.draw(&s, x, y, centered(font_size, color));
     }
+
+    let font_size = target_height / 16.0;
+    let s = locales.get("enter_name_help");
+    let y = target_height * 3.0 / 4.0;
+    let style = centered(font_size, DARKGRAY);
+    text.draw_wrapped(s, center, y, target_width * 0.9, style);
 }
 
-fn draw_game(
-    game: &Game,
-    camera_offset_x_px: &mut f32,
+fn draw_high_scores(
     target_width: f32,
     target_height: f32,
-    ctx: &mut Context,
+    high_scores: &HighScores,
+    highlight: Option<usize>,
+    locales: &Locales,
+    text: &Text,
 ) {
-    let background_color = game
-        .map_current
-        .as_ref()
-        .map(|x| x.background())
-        .unwrap_or_default();
-
-    let grid_height = game.grid_height;
-    let grid_width = game.grid_width;
-    let cell_size_px = target_height / grid_height as f32;
-    let grid_width_px = grid_width as f32 * cell_size_px;
-
-    let player_pos = game
-        .entities
-        .get(&game.player)
-        .map(|e| e.pos)
-        .unwrap_or_default();
-    let player_pos_px = player_pos.x * cell_size_px;
-    *camera_offset_x_px = player_pos_px - target_width / 2.0;
-
-    if target_width < grid_width_px {
-        *camera_offset_x_px = camera_offset_x_px.clamp(0.0, grid_width_px - target_width);
-    }
-
-    clear_background(Color::from_rgba(
-        background_color.0,
-        background_color.1,
-        background_color.2,
-        255,
-    ));
-
-    draw_grid(
-        game,
-        &ctx.assets.tileset,
-        *camera_offset_x_px,
-        cell_size_px,
-        target_width,
-        false,
-    );
+    clear_background(BLACK);
+    let center = target_width / 2.0;
+    let font_size = target_height / 8.0;
+    let y = target_height / 6.0;
+    let s = locales.get("high_scores");
+    text.draw(s, center, y, heading(font_size));
 
-    // Draw entities
-    for e in game.entities.values() {
-        let x = (e.pos.x - 0.5) * cell_size_px - *camera_offset_x_px;
-        let y = (e.pos.y - 0.5) * cell_size_px;
-        let index = match e.variant {
-            game::EntityVariant::Unknown => 1.0,
-            game::EntityVariant::Player { skin } => SKIN_INDEX[skin as usize],
-            game::EntityVariant::Goal => 2.0,
-            game::EntityVariant::Coin => 21.0,
-            game::EntityVariant::Cloud => 81.0,
+    let font_size = target_height / 16.0;
+    let line_height = font_size * 1.1;
+    for i in 0..highscores::HIGH_SCORES_MAX {
+        let s = match high_scores.entries.get(i) {
+            Some(entry) => format!("{:>2}. {:<3} {:>8}", i + 1, entry.name, entry.score),
+            None => format!("{:>2}. --- {:>8}", i + 1, 0),
         };
-        let flip_x = matches!(e.dir_x, game::DirX::Left);
-        draw_atlas(
-            &ctx.assets.tileset,
-            x,
-            y,
-            index,
-            WHITE,
-            Vec2::new(cell_size_px, cell_size_px),
-            flip_x,
-            false,
-        );
+        let color = if highlight == Some(i) { YELLOW } else { WHITE };
+        let y = target_height / 6.0 + font_size * 2.0 + line_height * i as f32;
+        text.draw(&s, center, y, centered(font_size, color));
     }
+}
 
-    draw_grid(
-        game,
-        &ctx.assets.tileset,
-        *camera_offset_x_px,
-        cell_size_px,
-        target_width,
-        true,
-    );
+fn draw_gameover(
+    target_width: f32,
+    target_height: f32,
+    score: u32,
+    menu: &Menu,
+    items: &[String],
+    locales: &Locales,
+    text: &Text,
+) {
+    clear_background(BLACK);
+    let center = target_width / 2.0;
+    let font_size = target_height / 8.0;
+    let s = locales.get("game_over");
+    let y = target_height / 2.0 - text.measure(s, font_size).height;
+    text.draw(s, center, y, heading(font_size));
 
-    draw_hud(game, target_width, target_height, ctx);
+    let font_size = target_height / 16.0;
+    let s = locales.format("final_score", &[score.to_string()]);
+    let y = target_height / 2.0 - text.measure(&s, font_size).height + font_size / 2.0;
+    text.draw(&s, center, y, centered(font_size, WHITE));
+
+    menu.draw(items, target_width, y + font_size * 2.5, font_size, text);
 }
 
-fn draw_hud(game: &Game, target_width: f32, target_height: f32, ctx: &mut Context) {
+fn draw_title(target_width: f32, target_height: f32, menu: &Menu, locales: &Locales, text: &Text) {
+    clear_background(BLACK);
+    let center = target_width / 2.0;
     let font_size = target_height / 8.0;
-    let margin = 16.0;
-    let transparent_color = Color::from_rgba(0, 0, 0, 255 / 4 * 2);
-    if !game.center_text.is_empty() {
-        let measure = measure_text(&game.center_text, None, font_size as u16, 1.0);
-        let x = target_width / 2.0 - measure.width / 2.0;
-        let y = target_height / 2.0 - measure.height;
-        let margin = 16.0;
-        draw_rectangle(
-            0.0,
-            y - margin,
-            target_width,
-            measure.height + margin * 2.0,
-            transparent_color,
-        );
-        draw_text_ex(
-            &game.center_text,
-            x,
-            y + measure.height,
-            TextParams {
-                font_size: font_size as u16,
-                ..Default::default()
-            },
-        );
-    }
+    let s = locales.get("title");
+    let y = target_height / 3.0 - text.measure(s, font_size).height;
+    text.draw(s, center, y, heading(font_size));
 
     let font_size = target_height / 16.0;
+    let s = locales.get("tagline");
+    let y = target_height / 3.0 - text.measure(s, font_size).height + font_size / 2.0;
+    text.draw(s, center, y, centered(font_size, WHITE));
 
-    // draw top bar
-    draw_rectangle(0.0, 0.0, target_width, font_size, transparent_color);
-
-    // draw SCORE
-    let score = game.score;
-    let s = &format!("SCORE: {score}");
-    let measure = measure_text(s, None, font_size as u16, 1.0);
-    let x = margin;
-    let y = margin + measure.height;
-    draw_text_ex(
-        s,
-        x,
-        y,
-        TextParams {
-            font_size: font_size as u16,
-            ..Default::default()
-        },
+    menu.draw(
+        &title_items(locales),
+        target_width,
+        y + font_size * 2.5,
+        font_size,
+        text,
     );
+}
 
-    // draw LIVES
-    let s = &format!("LIVES: {}", game.lives_extra);
-    let measure = measure_text(s, None, font_size as u16, 1.0);
-    let x = target_width / 3.0 - measure.width / 2.0;
-    let y = margin + measure.height;
-    draw_text_ex(
-        s,
-        x,
-        y,
-        TextParams {
-            font_size: font_size as u16,
-            ..Default::default()
-        },
-    );
+fn draw_loading(target_width: f32, target_height: f32, progress: f32) {
+    clear_background(BLACK);
+    let width = target_width * 0.6;
+    let height = target_height / 32.0;
+    let x = (target_width - width) / 2.0;
+    let y = (target_height - height) / 2.0;
+    draw_rectangle_lines(x, y, width, height, 4.0, DARKGRAY);
+    draw_rectangle(x, y, width * progress.clamp(0.0, 1.0), height, WHITE);
+}
 
-    // draw LIVES
-    let s = &format!("COINS: {}", game.coins);
-    let measure = measure_text(s, None, font_size as u16, 1.0);
-    let x = target_width / 2.0; // - measure.width / 2.0;
-    let y = margin + measure.height;
-    draw_text_ex(
-        s,
-        x,
-        y,
-        TextParams {
-            font_size: font_size as u16,
-            ..Default::default()
-        },
-    );
+fn draw_error(
+    target_width: f32,
+    target_height: f32,
+    error: &AssetError,
+    locales: &Locales,
+    text: &Text,
+) {
+    clear_background(BLACK);
+    let center = target_width / 2.0;
+    let font_size = target_height / 8.0;
+    let y = target_height / 4.0;
+    text.draw(locales.get("error"), center, y, heading(font_size));
+
+    // paths and causes are not translated, they are for bug reports
+    let font_size = target_height / 24.0;
+    let y = target_height / 4.0 + font_size * 3.0;
+    let style = centered(font_size, RED);
+    text.draw_wrapped(&error.to_string(), center, y, target_width * 0.9, style);
 
-    // draw level x OF 6
-    let s = &format!("LEVEL {} OF {}", game.level_current + 1, ctx.map_list.len());
-    let measure = measure_text(s, None, font_size as u16, 1.0);
-    let x = target_width - measure.width - margin;
-    let y = margin + measure.height;
-    draw_text_ex(
-        s,
-        x,
+    let font_size = target_height / 16.0;
+    let y = target_height * 3.0 / 4.0;
+    let style = centered(font_size, DARKGRAY);
+    text.draw_wrapped(
+        locales.get("error_help"),
+        center,
         y,
-        TextParams {
-            font_size: font_size as u16,
-            ..Default::default()
-        },
+        target_width * 0.9,
+        style,
     );
 }
 
-fn draw_grid(
+fn draw_game(
     game: &Game,
-    atlas: &Atlas,
-    camera_offset_x_px: f32,
-    cell_size_px: f32,
+    camera_offset_px: &mut Vec2,
     target_width: f32,
-    is_foreground: bool,
+    target_height: f32,
+    ctx: &Context,
 ) {
-    let start_x = (camera_offset_x_px / cell_size_px) as u32;
-    let end_x = ((camera_offset_x_px + target_width) / cell_size_px) as u32 + 1;
-    for y in 0..game.grid_height {
-        for x in start_x..end_x {
-            let x_px = x as f32 * cell_size_px - camera_offset_x_px;
-            let y_px = y as f32 * cell_size_px;
-            if let Some(cell) = game.grid.get((x as i32, y as i32)) {
-                if cell.is_foreground == is_foreground {
-                    draw_atlas(
-                        atlas,
-                        x_px,
-                        y_px,
-                        cell.variant as f32,
-                        WHITE,
-                        Vec2::new(cell_size_px, cell_size_px),
-                        false,
-                        false,
-                    );
-                }
-            }
-        }
-    }
+    let view = View {
+        animations: ctx.assets.atlases.iter().map(|a| &a.animations).collect(),
+        locales: &ctx.locales,
+        particles: &ctx.particles,
+        split: ctx.split.as_ref(),
+        map_count: ctx.map_list.len(),
+    };
+    let list = view::build_game(game, camera_offset_px, target_width, target_height, &view);
+    render::execute(&list, &ctx.assets.atlases, &ctx.assets.text);
 }
 
 fn blit_render_target(texture: &Texture2D, target_width: f32, target_height: f32) {
@@ -776,6 +1382,7 @@ fn blit_render_target(texture: &Texture2D, target_width: f32, target_height: f32
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn draw_atlas(
     atlas: &Atlas,
     x: f32,
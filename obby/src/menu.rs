@@ -0,0 +1,93 @@
+use game::Keys;
+use macroquad::prelude::*;
+
+use crate::{
+    render::Align,
+    text::{LINE_SPACING, Text, TextStyle},
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Axis {
+    Vertical,
+    Horizontal,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MenuEvent {
+    Confirm(usize),
+    Back,
+    /// left or right pressed on the focused item of a vertical menu
+    Adjust(usize, i32),
+}
+
+/// A list of items with a focused entry that wraps around at both ends.
+///
+/// The menu only tracks focus, the caller owns the item texts so the menu can
+/// live inside `AppState`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Menu {
+    pub focus: usize,
+    pub len: usize,
+    pub axis: Axis,
+}
+
+impl Menu {
+    pub fn new(len: usize) -> Self {
+        Self {
+            focus: 0,
+            len,
+            axis: Axis::Vertical,
+        }
+    }
+
+    pub fn horizontal(len: usize) -> Self {
+        Self {
+            axis: Axis::Horizontal,
+            ..Self::new(len)
+        }
+    }
+
+    pub fn with_focus(mut self, focus: usize) -> Self {
+        self.focus = focus % self.len.max(1);
+        self
+    }
+
+    /// Moves the focus and returns what the player did, `pressed` tells if a key was pressed this frame.
+    pub fn update(&mut self, pressed: impl Fn(Keys) -> bool) -> Option<MenuEvent> {
+        if self.len == 0 {
+            return None;
+        }
+        let (prev, next) = match self.axis {
+            Axis::Vertical => (Keys::Up, Keys::Down),
+            Axis::Horizontal => (Keys::Left, Keys::Right),
+        };
+        if pressed(Keys::Confirm) {
+            return Some(MenuEvent::Confirm(self.focus));
+        }
+        if pressed(Keys::Back) {
+            return Some(MenuEvent::Back);
+        }
+        if pressed(next) {
+            self.focus = (self.focus + 1) % self.len;
+        } else if pressed(prev) {
+            self.focus = (self.focus + self.len - 1) % self.len;
+        } else if self.axis == Axis::Vertical {
+            if pressed(Keys::Left) {
+                return Some(MenuEvent::Adjust(self.focus, -1));
+            } else if pressed(Keys::Right) {
+                return Some(MenuEvent::Adjust(self.focus, 1));
+            }
+        }
+        None
+    }
+
+    /// Draws `items` as a centered vertical list starting at `y`.
+    pub fn draw(&self, items: &[String], target_width: f32, y: f32, font_size: f32, text: &Text) {
+        for (i, s) in items.iter().enumerate() {
+            let color = if i == self.focus { WHITE } else { DARKGRAY };
+            let style = TextStyle::new(font_size, color).with_align(Align::Center);
+            let y = y + font_size * LINE_SPACING * i as f32;
+            text.draw(s, target_width / 2.0, y, style);
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+
+use macroquad::{
+    audio::{
+        PlaySoundParams, Sound, load_sound_from_bytes, play_sound, set_sound_volume, stop_sound,
+    },
+    math::Vec2,
+};
+
+use crate::{loader::Loader, storage::Storage};
+
+const MIXER_KEY: &str = "audio.cfg";
+
+/// Seconds it takes to fade a music track fully in or out.
+const CROSSFADE_SEC: f32 = 1.5;
+
+/// Distance in tiles within which world sounds play at full volume.
+const HEARING_NEAR: f32 = 6.0;
+/// Distance in tiles beyond which world sounds are silent.
+const HEARING_FAR: f32 = 24.0;
+/// Horizontal distance in tiles at which a sound is panned fully to one side.
+const PAN_DISTANCE: f32 = 16.0;
+
+/// Returns the gain and pan of a sound at `pos` heard from `listener`, both in tiles.
+pub fn spatialize(listener: Vec2, pos: Vec2) -> (f32, f32) {
+    let distance = listener.distance(pos);
+    let gain = 1.0 - (distance - HEARING_NEAR) / (HEARING_FAR - HEARING_NEAR);
+    let pan = (pos.x - listener.x) / PAN_DISTANCE;
+    (gain.clamp(0.0, 1.0), pan.clamp(-1.0, 1.0))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Bus {
+    Master,
+    Music,
+    Sfx,
+}
+
+impl Bus {
+    pub const ALL: [Bus; 3] = [Bus::Master, Bus::Music, Bus::Sfx];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Bus::Master => "master",
+            Bus::Music => "music",
+            Bus::Sfx => "sfx",
+        }
+    }
+}
+
+struct Track {
+    name: String,
+    sound: Sound,
+    gain: f32,
+    fading_in: bool,
+}
+
+/// Volume buses and the looping music tracks.
+///
+/// Volumes and mute are saved to `audio.cfg` as `bus, volume` lines and a
+/// `mute, true|false` line. Music tracks are loaded the first time they are
+/// asked for and crossfade when the wanted track changes.
+pub struct Mixer {
+    volumes: [f32; 3],
+    pub muted: bool,
+    music: HashMap<String, Option<Sound>>,
+    wanted: Option<String>,
+    tracks: Vec<Track>,
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Self {
+            volumes: [1.0, 0.6, 1.0],
+            muted: false,
+            music: HashMap::default(),
+            wanted: None,
+            tracks: Vec::default(),
+        }
+    }
+}
+
+impl Mixer {
+    pub fn volume(&self, bus: Bus) -> f32 {
+        self.volumes[bus as usize]
+    }
+
+    pub fn set_volume(&mut self, bus: Bus, volume: f32) {
+        self.volumes[bus as usize] = volume.clamp(0.0, 1.0);
+    }
+
+    /// Returns the effective volume of `bus` after master and mute are applied.
+    pub fn gain(&self, bus: Bus) -> f32 {
+        if self.muted {
+            return 0.0;
+        }
+        match bus {
+            Bus::Master => self.volume(Bus::Master),
+            bus => self.volume(Bus::Master) * self.volume(bus),
+        }
+    }
+
+    /// Sets the music track that should be playing, `None` fades the music out.
+    pub fn play_music(&mut self, path: Option<&str>) {
+        if self.wanted.as_deref() != path {
+            self.wanted = path.map(str::to_string);
+        }
+    }
+
+    /// Fetches the wanted music track with `loader` and decodes it once it arrives.
+    pub async fn load_pending(&mut self, loader: &mut Loader) {
+        let Some(path) = &self.wanted else {
+            return;
+        };
+        if self.music.contains_key(path) {
+            return;
+        }
+        loader.request(path);
+        let sound = match loader.take(path) {
+            None => return,
+            Some(Ok(bytes)) => match load_sound_from_bytes(&bytes).await {
+                Ok(sound) => Some(sound),
+                Err(err) => {
+                    println!("failed to load music '{path}': {err}");
+                    None
+                }
+            },
+            Some(Err(err)) => {
+                println!("failed to load music {err}");
+                None
+            }
+        };
+        self.music.insert(path.clone(), sound);
+    }
+
+    /// Advances the crossfade and applies the music volume.
+    pub fn update(&mut self, dt: f32) {
+        let current = self.tracks.iter().find(|t| t.fading_in).map(|t| &t.name);
+        if current != self.wanted.as_ref() {
+            for track in self.tracks.iter_mut() {
+                track.fading_in = false;
+            }
+            if let Some(wanted) = &self.wanted {
+                if let Some(track) = self.tracks.iter_mut().find(|t| t.name == *wanted) {
+                    track.fading_in = true;
+                } else if let Some(Some(sound)) = self.music.get(wanted) {
+                    play_sound(
+                        sound,
+                        PlaySoundParams {
+                            looped: true,
+                            volume: 0.0,
+                        },
+                    );
+                    self.tracks.push(Track {
+                        name: wanted.clone(),
+                        sound: sound.clone(),
+                        gain: 0.0,
+                        fading_in: true,
+                    });
+                }
+            }
+        }
+
+        let gain = self.gain(Bus::Music);
+        let step = dt / CROSSFADE_SEC;
+        for track in self.tracks.iter_mut() {
+            track.gain = if track.fading_in {
+                (track.gain + step).min(1.0)
+            } else {
+                (track.gain - step).max(0.0)
+            };
+            set_sound_volume(&track.sound, track.gain * gain);
+        }
+        self.tracks.retain(|track| {
+            let done = !track.fading_in && track.gain <= 0.0;
+            if done {
+                stop_sound(&track.sound);
+            }
+            !done
+        });
+    }
+
+    pub fn load(storage: &dyn Storage) -> Self {
+        let mut mixer = Self::default();
+        for line in storage.load(MIXER_KEY).unwrap_or_default().lines() {
+            let mut cols = line.split(",").map(str::trim);
+            let (Some(key), Some(value)) = (cols.next(), cols.next()) else {
+                continue;
+            };
+            if key == "mute" {
+                mixer.muted = value == "true";
+            } else if let Some(bus) = Bus::ALL.iter().find(|b| b.name() == key)
+                && let Ok(volume) = value.parse()
+            {
+                mixer.set_volume(*bus, volume);
+            }
+        }
+        mixer
+    }
+
+    pub fn save(&self, storage: &mut dyn Storage) {
+        let mut s = String::new();
+        for bus in Bus::ALL {
+            s += &format!("{}, {}\n", bus.name(), self.volume(bus));
+        }
+        s += &format!("mute, {}\n", self.muted);
+        storage.save(MIXER_KEY, &s);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spatialize_gain() {
+        let listener = Vec2::new(10.0, 5.0);
+        assert_eq!(spatialize(listener, listener), (1.0, 0.0));
+        assert_eq!(
+            spatialize(listener, listener + Vec2::new(0.0, HEARING_NEAR)).0,
+            1.0
+        );
+        let halfway = (HEARING_NEAR + HEARING_FAR) / 2.0;
+        assert_eq!(
+            spatialize(listener, listener + Vec2::new(0.0, halfway)).0,
+            0.5
+        );
+        assert_eq!(
+            spatialize(listener, listener + Vec2::new(0.0, HEARING_FAR * 2.0)).0,
+            0.0
+        );
+    }
+
+    #[test]
+    fn spatialize_pan() {
+        let listener = Vec2::new(10.0, 5.0);
+        let half = PAN_DISTANCE / 2.0;
+        assert_eq!(
+            spatialize(listener, listener + Vec2::new(-half, 0.0)).1,
+            -0.5
+        );
+        assert_eq!(spatialize(listener, listener + Vec2::new(half, 3.0)).1, 0.5);
+        assert_eq!(
+            spatialize(listener, listener + Vec2::new(PAN_DISTANCE * 3.0, 0.0)).1,
+            1.0
+        );
+    }
+}
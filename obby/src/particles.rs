@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+
+use macroquad::{color::Color, math::Vec2, rand::RandGenerator};
+
+/// Upper bound on live particles, emitting more while full drops the new ones.
+const PARTICLES_MAX: usize = 1024;
+
+/// How an emitter spawns its particles, positions and speeds are in tiles.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EmitterDef {
+    pub name: String,
+    pub count: u32,
+    pub life_sec: (f32, f32),
+    pub speed: (f32, f32),
+    /// Direction in degrees, 0 is right and -90 is up.
+    pub angle: f32,
+    /// Spread around `angle` in degrees, 360 emits in every direction.
+    pub spread: f32,
+    pub gravity: f32,
+    /// Atlas index to draw, plain squares when `None`.
+    pub frame: Option<u32>,
+    pub color: (Color, Color),
+    pub size: (f32, f32),
+}
+
+impl EmitterDef {
+    /// Parses a line of the form `name, option=value, ...`.
+    ///
+    /// Options are `count=8`, `life=0.3..0.6`, `speed=2..5`, `angle=-90`,
+    /// `spread=360`, `gravity=20`, `frame=21`, `color=ffff00ff..ffff0000` and
+    /// `size=0.3..0.1`, ranges may also be a single value.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut cols = line.split(",").map(str::trim);
+        let name = cols
+            .next()
+            .filter(|n| !n.is_empty())
+            .ok_or("missing name")?;
+        let mut def = EmitterDef {
+            name: name.to_string(),
+            count: 8,
+            life_sec: (0.5, 0.5),
+            speed: (1.0, 1.0),
+            angle: -90.0,
+            spread: 360.0,
+            gravity: 0.0,
+            frame: None,
+            color: (
+                Color::new(1.0, 1.0, 1.0, 1.0),
+                Color::new(1.0, 1.0, 1.0, 0.0),
+            ),
+            size: (0.2, 0.2),
+        };
+        for col in cols.filter(|c| !c.is_empty()) {
+            let (option, value) = col
+                .split_once("=")
+                .ok_or_else(|| format!("expected option=value, found '{col}'"))?;
+            let (option, value) = (option.trim(), value.trim());
+            let invalid = || format!("invalid value '{value}' for '{option}'");
+            let range = || -> Result<(f32, f32), String> {
+                let (a, b) = value.split_once("..").unwrap_or((value, value));
+                Ok((
+                    a.trim().parse().map_err(|_| invalid())?,
+                    b.trim().parse().map_err(|_| invalid())?,
+                ))
+            };
+            match option {
+                "count" => def.count = value.parse().map_err(|_| invalid())?,
+                "life" => def.life_sec = range()?,
+                "speed" => def.speed = range()?,
+                "angle" => def.angle = value.parse().map_err(|_| invalid())?,
+                "spread" => def.spread = value.parse().map_err(|_| invalid())?,
+                "gravity" => def.gravity = value.parse().map_err(|_| invalid())?,
+                "frame" => def.frame = Some(value.parse().map_err(|_| invalid())?),
+                "size" => def.size = range()?,
+                "color" => {
+                    let (a, b) = value.split_once("..").unwrap_or((value, value));
+                    def.color = (
+                        parse_color(a.trim()).ok_or_else(invalid)?,
+                        parse_color(b.trim()).ok_or_else(invalid)?,
+                    );
+                }
+                _ => return Err(format!("unknown option '{option}'")),
+            }
+        }
+        if def.life_sec.0 <= 0.0 || def.life_sec.1 < def.life_sec.0 {
+            return Err("life must be positive and min..max".to_string());
+        }
+        Ok(def)
+    }
+}
+
+/// Parses `rrggbb` or `rrggbbaa` hex colors.
+fn parse_color(s: &str) -> Option<Color> {
+    // `from_str_radix` would take a leading `+`
+    if !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(s.get(i..i + 2)?, 16).ok();
+    let a = match s.len() {
+        6 => 255,
+        8 => channel(6)?,
+        _ => return None,
+    };
+    Some(Color::from_rgba(channel(0)?, channel(2)?, channel(4)?, a))
+}
+
+fn lerp_color(a: Color, b: Color, t: f32) -> Color {
+    Color::new(
+        a.r + (b.r - a.r) * t,
+        a.g + (b.g - a.g) * t,
+        a.b + (b.b - a.b) * t,
+        a.a + (b.a - a.a) * t,
+    )
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Particle {
+    pub pos: Vec2,
+    pub vel: Vec2,
+    pub age_sec: f32,
+    pub life_sec: f32,
+    /// Index of the emitter that spawned the particle.
+    pub emitter: usize,
+    pub alive: bool,
+}
+
+/// What to draw for a live particle.
+pub struct ParticleSprite {
+    pub pos: Vec2,
+    pub size: f32,
+    pub color: Color,
+    pub frame: Option<u32>,
+}
+
+/// A fixed pool of particles and the emitters read from `particles.csv`.
+///
+/// Simulation only moves numbers around, drawing goes through [`Particles::sprites`]
+/// so the two stay apart. Particles are spread with their own `rng`, the same seed
+/// spawns the same particles.
+pub struct Particles {
+    emitters: Vec<EmitterDef>,
+    names: HashMap<String, usize>,
+    pool: Vec<Particle>,
+    rng: RandGenerator,
+}
+
+impl Particles {
+    pub fn new(emitters: Vec<EmitterDef>, rng: RandGenerator) -> Self {
+        let names = emitters
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (e.name.clone(), i))
+            .collect();
+        Self {
+            emitters,
+            names,
+            pool: vec![Particle::default(); PARTICLES_MAX],
+            rng,
+        }
+    }
+
+    /// Parses the emitters, printing and skipping lines that are malformed.
+    pub fn parse(path: &str, text: &str, rng: RandGenerator) -> Self {
+        let mut emitters = Vec::default();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match EmitterDef::parse(line) {
+                Ok(def) => emitters.push(def),
+                Err(err) => println!("{path}:{}: {err}", n + 1),
+            }
+        }
+        Self::new(emitters, rng)
+    }
+
+    /// Spawns the particles of emitter `name` at `pos`.
+    pub fn emit(&mut self, name: &str, pos: Vec2) {
+        let Some(&emitter) = self.names.get(name) else {
+            return;
+        };
+        let def = &self.emitters[emitter];
+        let mut free = self.pool.iter_mut().filter(|p| !p.alive);
+        for _ in 0..def.count {
+            let Some(p) = free.next() else {
+                return;
+            };
+            let angle = (def.angle + self.rng.gen_range(-0.5, 0.5) * def.spread).to_radians();
+            let speed = self.rng.gen_range(def.speed.0, def.speed.1);
+            *p = Particle {
+                pos,
+                vel: Vec2::from_angle(angle) * speed,
+                age_sec: 0.0,
+                life_sec: self.rng.gen_range(def.life_sec.0, def.life_sec.1),
+                emitter,
+                alive: true,
+            };
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        for p in self.pool.iter_mut().filter(|p| p.alive) {
+            p.age_sec += dt;
+            if p.age_sec >= p.life_sec {
+                p.alive = false;
+                continue;
+            }
+            p.vel.y += self.emitters[p.emitter].gravity * dt;
+            p.pos += p.vel * dt;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for p in self.pool.iter_mut() {
+            p.alive = false;
+        }
+    }
+
+    pub fn alive(&self) -> impl Iterator<Item = &Particle> {
+        self.pool.iter().filter(|p| p.alive)
+    }
+
+    /// Returns size, color and frame of every live particle at its point in life.
+    pub fn sprites(&self) -> impl Iterator<Item = ParticleSprite> + '_ {
+        self.alive().map(|p| {
+            let def = &self.emitters[p.emitter];
+            let t = (p.age_sec / p.life_sec).clamp(0.0, 1.0);
+            ParticleSprite {
+                pos: p.pos,
+                size: def.size.0 + (def.size.1 - def.size.0) * t,
+                color: lerp_color(def.color.0, def.color.1, t),
+                frame: def.frame,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rng(seed: u64) -> RandGenerator {
+        let rng = RandGenerator::new();
+        rng.srand(seed);
+        rng
+    }
+
+    fn particles(line: &str) -> Particles {
+        Particles::new(vec![EmitterDef::parse(line).unwrap()], rng(7))
+    }
+
+    #[test]
+    fn parse_options() {
+        let def = EmitterDef::parse(
+            "coin, count=10, life=0.3..0.5, speed=3, angle=45, spread=90, gravity=15, frame=21, color=ff000080..00ff00, size=0.4..0.1",
+        )
+        .unwrap();
+        assert_eq!(def.name, "coin");
+        assert_eq!(def.count, 10);
+        assert_eq!(def.life_sec, (0.3, 0.5));
+        assert_eq!(def.speed, (3.0, 3.0));
+        assert_eq!((def.angle, def.spread, def.gravity), (45.0, 90.0, 15.0));
+        assert_eq!(def.frame, Some(21));
+        assert_eq!(def.color.0, Color::from_rgba(255, 0, 0, 128));
+        assert_eq!(def.color.1, Color::from_rgba(0, 255, 0, 255));
+        assert_eq!(def.size, (0.4, 0.1));
+        assert_eq!(EmitterDef::parse("jump").unwrap().count, 8);
+    }
+
+    #[test]
+    fn parse_colors() {
+        assert_eq!(
+            parse_color("ff8000"),
+            Some(Color::from_rgba(255, 128, 0, 255))
+        );
+        assert_eq!(
+            parse_color("FF800040"),
+            Some(Color::from_rgba(255, 128, 0, 64))
+        );
+        for bad in [
+            "",
+            "fff",
+            "ff800",
+            "ff80000",
+            "ff8000400",
+            "+fffffff",
+            "+fffff",
+            "ff+f00",
+            "#ff8000",
+            "ff80g0",
+            "ø8000",
+        ] {
+            assert_eq!(parse_color(bad), None, "{bad}");
+        }
+        assert!(EmitterDef::parse("coin, color=+fffffff").is_err());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(EmitterDef::parse("").is_err());
+        assert!(EmitterDef::parse("coin, count").is_err());
+        assert!(EmitterDef::parse("coin, sparkle=1").is_err());
+        assert!(EmitterDef::parse("coin, count=-1").is_err());
+        assert!(EmitterDef::parse("coin, color=fff").is_err());
+        assert!(EmitterDef::parse("coin, life=0").is_err());
+        assert!(EmitterDef::parse("coin, life=0.5..0.2").is_err());
+    }
+
+    #[test]
+    fn particles_live_for_their_life() {
+        let mut particles = particles("coin, count=4, life=0.5, speed=2");
+        particles.emit("coin", Vec2::new(3.0, 4.0));
+        particles.emit("unknown", Vec2::ZERO);
+        assert_eq!(particles.alive().count(), 4);
+
+        particles.update(0.25);
+        assert_eq!(particles.alive().count(), 4);
+        for p in particles.alive() {
+            assert!((p.pos.distance(Vec2::new(3.0, 4.0)) - 0.5).abs() < 0.1);
+        }
+        let sprites: Vec<ParticleSprite> = particles.sprites().collect();
+        assert_eq!(sprites.len(), 4);
+
+        particles.update(0.3);
+        assert_eq!(particles.alive().count(), 0);
+    }
+
+    #[test]
+    fn full_pool_drops_new_particles() {
+        let mut particles = particles("burst, count=300, life=1");
+        for _ in 0..4 {
+            particles.emit("burst", Vec2::ZERO);
+        }
+        assert_eq!(particles.alive().count(), PARTICLES_MAX);
+
+        particles.update(1.0);
+        particles.emit("burst", Vec2::ZERO);
+        assert_eq!(particles.alive().count(), 300);
+        particles.clear();
+        assert_eq!(particles.alive().count(), 0);
+    }
+
+    #[test]
+    fn same_seed_same_particles() {
+        let line = "coin, count=5, life=0.2..0.8, speed=1..4, spread=360";
+        let mut a = particles(line);
+        let mut b = particles(line);
+        for particles in [&mut a, &mut b] {
+            particles.emit("coin", Vec2::ZERO);
+            particles.update(0.1);
+        }
+        let pos = |p: &Particles| p.alive().map(|p| (p.pos, p.life_sec)).collect::<Vec<_>>();
+        assert_eq!(pos(&a), pos(&b));
+    }
+}
@@ -0,0 +1,147 @@
+use std::{collections::HashMap, rc::Rc};
+
+use game::Ghost;
+
+use crate::storage::Storage;
+
+const BEST_TIMES_KEY: &str = "best_times.csv";
+
+/// Personal best level times for time trial, keyed by map path.
+#[derive(Default)]
+pub struct BestTimes {
+    pub times: HashMap<String, f32>,
+}
+
+/// Result of a finished time trial level compared against the personal best.
+#[derive(Clone, Copy)]
+pub struct Split {
+    pub time_sec: f32,
+    pub best_sec: Option<f32>,
+}
+
+impl Split {
+    /// Seconds gained (negative) or lost (positive) against the previous best.
+    pub fn delta_sec(&self) -> Option<f32> {
+        self.best_sec.map(|best| self.time_sec - best)
+    }
+
+    pub fn is_record(&self) -> bool {
+        self.best_sec.is_none_or(|best| self.time_sec < best)
+    }
+}
+
+impl BestTimes {
+    pub fn load(storage: &dyn Storage) -> Self {
+        let mut times = HashMap::default();
+        for line in storage.load(BEST_TIMES_KEY).unwrap_or_default().lines() {
+            // the time is split off the end as map paths may contain commas
+            let Some((map, time)) = line.rsplit_once(',') else {
+                continue;
+            };
+            if let Ok(time) = time.trim().parse::<f32>() {
+                times.insert(map.trim().to_string(), time);
+            }
+        }
+        Self { times }
+    }
+
+    pub fn save(&self, storage: &mut dyn Storage) {
+        let mut s = String::new();
+        for (map, time) in self.times.iter() {
+            s += &format!("{map}, {time}\n");
+        }
+        storage.save(BEST_TIMES_KEY, &s);
+    }
+
+    /// Records `time_sec` for `map` and returns the split against the previous best.
+    pub fn submit(&mut self, map: &str, time_sec: f32) -> Split {
+        let split = Split {
+            time_sec,
+            best_sec: self.times.get(map).copied(),
+        };
+        if split.is_record() {
+            self.times.insert(map.to_string(), time_sec);
+        }
+        split
+    }
+}
+
+/// Formats seconds as `M:SS.cc`.
+pub fn format_time(sec: f32) -> String {
+    let centis = (sec.max(0.0) * 100.0).round() as u32;
+    format!(
+        "{}:{:02}.{:02}",
+        centis / 6000,
+        centis / 100 % 60,
+        centis % 100
+    )
+}
+
+fn ghost_key(map: &str) -> String {
+    format!("ghost_{}.txt", map.replace(['/', '\\', '.'], "_"))
+}
+
+/// Best run per map replayed as a ghost in time trial.
+#[derive(Default)]
+pub struct Ghosts {
+    pub ghosts: HashMap<String, Option<Rc<Ghost>>>,
+}
+
+impl Ghosts {
+    pub fn get(&mut self, map: &str, storage: &dyn Storage) -> Option<Rc<Ghost>> {
+        self.ghosts
+            .entry(map.to_string())
+            .or_insert_with(|| {
+                let text = storage.load(&ghost_key(map))?;
+                match Ghost::parse(&text) {
+                    Ok(ghost) => Some(Rc::new(ghost)),
+                    Err(err) => {
+                        println!("failed to load the ghost of {map}: {err}");
+                        None
+                    }
+                }
+            })
+            .clone()
+    }
+
+    pub fn save(&mut self, map: &str, ghost: Ghost, storage: &mut dyn Storage) {
+        storage.save(&ghost_key(map), &ghost.to_text());
+        self.ghosts.insert(map.to_string(), Some(Rc::new(ghost)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn best_times_round_trip() {
+        let mut storage = MemoryStorage::default();
+        let mut best = BestTimes::default();
+        best.submit("res/maps/map01.tmx", 12.5);
+        best.submit("res/maps/a,b.tmx", 3.25);
+        best.save(&mut storage);
+
+        let loaded = BestTimes::load(&storage);
+        assert_eq!(loaded.times.len(), 2);
+        assert_eq!(loaded.times["res/maps/map01.tmx"], 12.5);
+        assert_eq!(loaded.times["res/maps/a,b.tmx"], 3.25);
+    }
+
+    #[test]
+    fn submit_keeps_the_best() {
+        let mut best = BestTimes::default();
+        assert!(best.submit("map", 10.0).is_record());
+        let split = best.submit("map", 11.0);
+        assert!(!split.is_record());
+        assert_eq!(split.delta_sec(), Some(1.0));
+        assert_eq!(best.times["map"], 10.0);
+    }
+
+    #[test]
+    fn format_time_pads() {
+        assert_eq!(format_time(65.432), "1:05.43");
+        assert_eq!(format_time(-1.0), "0:00.00");
+    }
+}
@@ -0,0 +1,139 @@
+use macroquad::prelude::*;
+
+use crate::{
+    assets::Atlas,
+    text::{Text, TextStyle},
+};
+
+/// Draw order, commands on lower layers are drawn first.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Layer {
+    Tiles,
+    Entities,
+    Particles,
+    Foreground,
+    Hud,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Shape {
+    /// A cell of one of the atlases, `pos` is the top left corner.
+    Sprite {
+        atlas: usize,
+        index: u32,
+        pos: Vec2,
+        size: Vec2,
+        color: Color,
+        flip_x: bool,
+        flip_y: bool,
+        /// Clockwise around the center in radians, applied after flipping.
+        rotation: f32,
+    },
+    Rect {
+        rect: Rect,
+        color: Color,
+    },
+    /// `pos.y` is the top of the text and `pos.x` is where it is aligned.
+    Text {
+        text: String,
+        pos: Vec2,
+        style: TextStyle,
+    },
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct DrawCommand {
+    pub layer: Layer,
+    pub shape: Shape,
+}
+
+/// Everything needed to draw a frame, built without touching the GPU.
+#[derive(Default, Clone, PartialEq, Debug)]
+pub struct DrawList {
+    pub clear: Option<Color>,
+    pub commands: Vec<DrawCommand>,
+}
+
+impl DrawList {
+    pub fn push(&mut self, layer: Layer, shape: Shape) {
+        self.commands.push(DrawCommand { layer, shape });
+    }
+
+    pub fn rect(&mut self, layer: Layer, rect: Rect, color: Color) {
+        self.push(layer, Shape::Rect { rect, color });
+    }
+
+    pub fn text(&mut self, layer: Layer, text: impl Into<String>, pos: Vec2, style: TextStyle) {
+        self.push(
+            layer,
+            Shape::Text {
+                text: text.into(),
+                pos,
+                style,
+            },
+        );
+    }
+
+    /// Returns the commands in draw order, keeping the push order within a layer.
+    pub fn sorted(&self) -> Vec<&DrawCommand> {
+        let mut commands: Vec<&DrawCommand> = self.commands.iter().collect();
+        commands.sort_by_key(|c| c.layer);
+        commands
+    }
+}
+
+/// Executes `list` with macroquad, sprites are taken from `atlases`.
+pub fn execute(list: &DrawList, atlases: &[Atlas], text: &Text) {
+    if let Some(color) = list.clear {
+        clear_background(color);
+    }
+    for command in list.sorted() {
+        match &command.shape {
+            Shape::Sprite {
+                atlas,
+                index,
+                pos,
+                size,
+                color,
+                flip_x,
+                flip_y,
+                rotation,
+            } => {
+                let Some(atlas) = atlases.get(*atlas) else {
+                    continue;
+                };
+                draw_texture_ex(
+                    &atlas.texture,
+                    pos.x.floor(),
+                    pos.y.floor(),
+                    *color,
+                    DrawTextureParams {
+                        dest_size: Some(*size),
+                        source: Some(atlas.index(*index as f32)),
+                        flip_x: *flip_x,
+                        flip_y: *flip_y,
+                        rotation: *rotation,
+                        ..Default::default()
+                    },
+                );
+            }
+            Shape::Rect { rect, color } => draw_rectangle(rect.x, rect.y, rect.w, rect.h, *color),
+            Shape::Text {
+                text: s,
+                pos,
+                style,
+            } => {
+                let offset_y = text.measure(s, style.font_size).offset_y;
+                text.draw(s, pos.x, pos.y + offset_y, *style);
+            }
+        }
+    }
+}
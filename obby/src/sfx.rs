@@ -0,0 +1,494 @@
+use std::collections::HashMap;
+
+use macroquad::{
+    audio::{PlaySoundParams, Sound, load_sound_from_bytes, play_sound},
+    rand,
+};
+
+use crate::loader::Loader;
+
+/// Number of pitch shifted copies loaded for entries with a pitch range.
+const PITCH_VARIANTS: usize = 5;
+
+/// Stereo positions of the panned copies of positional sounds, from left to right.
+const PAN_POSITIONS: [f32; 3] = [-0.6, 0.0, 0.6];
+
+/// Options of one `sfx.csv` entry, parsed before any sound is loaded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SfxDef {
+    pub key: String,
+    pub paths: Vec<String>,
+    pub volume: f32,
+    pub pitch: (f32, f32),
+    /// Maximum number of instances playing at once, zero means no limit.
+    pub max_instances: usize,
+    pub cooldown_sec: f32,
+    /// Panned to where the sound happens, which loads a stereo copy per pan position.
+    pub positional: bool,
+}
+
+impl SfxDef {
+    /// Parses a line of the form `key, file, file, ..., option=value, ...`.
+    ///
+    /// Options are `volume=0.8`, `pitch=0.9..1.1` (or a single `pitch=1.2`),
+    /// `max=3`, `cooldown=0.05` and `positional=true`. Entries with a pitch or
+    /// that are positional must only list WAV files.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut cols = line.split(",").map(str::trim);
+        let key = cols.next().filter(|k| !k.is_empty()).ok_or("missing key")?;
+        let mut def = SfxDef {
+            key: key.to_string(),
+            paths: Vec::default(),
+            volume: 1.0,
+            pitch: (1.0, 1.0),
+            max_instances: 0,
+            cooldown_sec: 0.0,
+            positional: false,
+        };
+        for col in cols.filter(|c| !c.is_empty()) {
+            let Some((option, value)) = col.split_once("=") else {
+                def.paths.push(col.to_string());
+                continue;
+            };
+            let (option, value) = (option.trim(), value.trim());
+            let invalid = || format!("invalid value '{value}' for '{option}'");
+            match option {
+                "volume" => def.volume = value.parse().map_err(|_| invalid())?,
+                "pitch" => {
+                    let (min, max) = value.split_once("..").unwrap_or((value, value));
+                    let min: f32 = min.trim().parse().map_err(|_| invalid())?;
+                    let max: f32 = max.trim().parse().map_err(|_| invalid())?;
+                    if min <= 0.0 || max < min {
+                        return Err(invalid());
+                    }
+                    def.pitch = (min, max);
+                }
+                "max" => def.max_instances = value.parse().map_err(|_| invalid())?,
+                "cooldown" => def.cooldown_sec = value.parse().map_err(|_| invalid())?,
+                "positional" => def.positional = value.parse().map_err(|_| invalid())?,
+                _ => return Err(format!("unknown option '{option}'")),
+            }
+        }
+        if def.paths.is_empty() {
+            return Err(format!("no files for '{key}'"));
+        }
+        // pitch and pan are changed by rewriting the header and samples of wav files
+        let option = match (def.pitch != (1.0, 1.0), def.positional) {
+            (true, _) => Some("pitch"),
+            (false, true) => Some("positional"),
+            (false, false) => None,
+        };
+        if let Some(option) = option
+            && let Some(path) = def.paths.iter().find(|p| !is_wav(p))
+        {
+            return Err(format!(
+                "{option} is only supported for wav files, not '{path}'"
+            ));
+        }
+        Ok(def)
+    }
+}
+
+fn is_wav(path: &str) -> bool {
+    path.to_lowercase().ends_with(".wav")
+}
+
+struct Clip {
+    /// One sound per entry in `PAN_POSITIONS`, or a single sound that can not be panned.
+    sounds: Vec<Sound>,
+    duration_sec: f32,
+}
+
+impl Clip {
+    fn panned(&self, pan: f32) -> &Sound {
+        if self.sounds.len() != PAN_POSITIONS.len() {
+            return &self.sounds[0];
+        }
+        let i = PAN_POSITIONS
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| (*a - pan).abs().total_cmp(&(*b - pan).abs()))
+            .map(|(i, _)| i)
+            .unwrap_or_default();
+        &self.sounds[i]
+    }
+}
+
+struct SfxEntry {
+    def: SfxDef,
+    /// Pitch variants of each file.
+    clips: Vec<Vec<Clip>>,
+    playing_until: Vec<f64>,
+    last_played: Option<f64>,
+}
+
+/// Sound effects loaded from `sfx.csv`, see [`SfxDef::parse`] for the format.
+///
+/// Problems with the manifest or a sound file are printed and the offending
+/// entry or file is skipped, the game still runs with the sounds that loaded.
+#[derive(Default)]
+pub struct Sfx {
+    entries: HashMap<String, SfxEntry>,
+}
+
+impl Sfx {
+    /// Returns the sound files listed in the manifest `text`, skipping malformed lines.
+    pub fn files(text: &str) -> Vec<String> {
+        text.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| SfxDef::parse(line).ok())
+            .flat_map(|def| def.paths)
+            .collect()
+    }
+
+    /// Decodes the manifest at `path` and its sound files, all fetched by `loader`.
+    pub async fn load(path: &str, loader: &Loader) -> Self {
+        let mut sfx = Sfx::default();
+        let text = match loader.text(path) {
+            Ok(text) => text,
+            Err(err) => {
+                println!("{err}");
+                return sfx;
+            }
+        };
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let def = match SfxDef::parse(line) {
+                Ok(def) => def,
+                Err(err) => {
+                    println!("{path}:{}: {err}", n + 1);
+                    continue;
+                }
+            };
+            let mut clips = Vec::default();
+            for file in def.paths.iter() {
+                let clips_res = match loader.bytes(file) {
+                    Ok(bytes) => load_clips(bytes, def.pitch, def.positional).await,
+                    Err(err) => Err(err.cause),
+                };
+                match clips_res {
+                    Ok(c) => clips.push(c),
+                    Err(err) => println!("{path}:{}: failed to load '{file}': {err}", n + 1),
+                }
+            }
+            if !clips.is_empty() {
+                let entry = SfxEntry {
+                    def,
+                    clips,
+                    playing_until: Vec::default(),
+                    last_played: None,
+                };
+                sfx.entries.insert(entry.def.key.clone(), entry);
+            }
+        }
+        sfx
+    }
+
+    /// Plays a random file and pitch of `key`, unless it is cooling down or
+    /// already playing its maximum number of instances.
+    ///
+    /// `pan` goes from -1 for left to 1 for right, only positional entries are panned.
+    pub fn play(&mut self, key: &str, looped: bool, volume: f32, pan: f32, now: f64) {
+        let Some(entry) = self.entries.get_mut(key) else {
+            return;
+        };
+        let def = &entry.def;
+        if entry
+            .last_played
+            .is_some_and(|t| now - t < def.cooldown_sec as f64)
+        {
+            return;
+        }
+        entry.playing_until.retain(|t| *t > now);
+        if def.max_instances > 0 && entry.playing_until.len() >= def.max_instances {
+            return;
+        }
+        let variants = &entry.clips[rand::gen_range(0, entry.clips.len())];
+        let clip = &variants[rand::gen_range(0, variants.len())];
+        play_sound(
+            clip.panned(pan),
+            PlaySoundParams {
+                looped,
+                volume: volume * def.volume,
+            },
+        );
+        let until = if looped {
+            f64::INFINITY
+        } else {
+            now + clip.duration_sec as f64
+        };
+        entry.playing_until.push(until);
+        entry.last_played = Some(now);
+    }
+}
+
+/// Decodes `bytes` once per pitch step in `pitch`, with panned copies when `positional`.
+///
+/// Pitch is changed by rewriting the sample rate of WAV files and panned copies
+/// are made of mono 16 bit WAV files, other files can do neither.
+async fn load_clips(
+    bytes: &[u8],
+    pitch: (f32, f32),
+    positional: bool,
+) -> Result<Vec<Clip>, String> {
+    let wav = wav_info(bytes);
+    let duration_sec = match &wav {
+        Some(wav) => wav.duration_sec(),
+        None => ogg_duration_sec(bytes).unwrap_or_default(),
+    };
+    let pitches: Vec<f32> = if pitch.0 == pitch.1 {
+        vec![pitch.0]
+    } else {
+        (0..PITCH_VARIANTS)
+            .map(|i| pitch.0 + (pitch.1 - pitch.0) * i as f32 / (PITCH_VARIANTS - 1) as f32)
+            .collect()
+    };
+    let mut clips = Vec::default();
+    for pitch in pitches {
+        let (bytes, duration_sec) = match &wav {
+            Some(wav) if pitch != 1.0 => (wav.with_pitch(bytes, pitch), duration_sec / pitch),
+            None if pitch != 1.0 => {
+                return Err("pitch is only supported for wav files".to_string());
+            }
+            _ => (bytes.to_vec(), duration_sec),
+        };
+        let mut sounds = Vec::default();
+        let variants = if positional {
+            let wav = wav_info(&bytes).ok_or("positional sounds must be wav files")?;
+            PAN_POSITIONS
+                .iter()
+                .map(|pan| wav.with_pan(&bytes, *pan))
+                .collect::<Option<Vec<_>>>()
+                .ok_or("positional sounds must be mono 16 bit wav files")?
+        } else {
+            vec![bytes]
+        };
+        for bytes in variants {
+            let sound = load_sound_from_bytes(&bytes)
+                .await
+                .map_err(|e| e.to_string())?;
+            sounds.push(sound);
+        }
+        clips.push(Clip {
+            sounds,
+            duration_sec,
+        });
+    }
+    Ok(clips)
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+struct WavInfo {
+    /// Offset of the `fmt ` chunk data.
+    fmt: usize,
+    byte_rate: u32,
+    /// Offset of the `data` chunk data.
+    data: usize,
+    data_len: u32,
+}
+
+impl WavInfo {
+    fn duration_sec(&self) -> f32 {
+        self.data_len as f32 / self.byte_rate.max(1) as f32
+    }
+
+    /// Returns a copy of the file with sample and byte rate scaled by `pitch`.
+    fn with_pitch(&self, bytes: &[u8], pitch: f32) -> Vec<u8> {
+        let mut bytes = bytes.to_vec();
+        for at in [self.fmt + 4, self.fmt + 8] {
+            let rate = read_u32(&bytes, at).unwrap_or_default();
+            let rate = (rate as f32 * pitch).round() as u32;
+            bytes[at..at + 4].copy_from_slice(&rate.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Returns a stereo copy of a mono 16 bit PCM file placed at `pan`.
+    fn with_pan(&self, bytes: &[u8], pan: f32) -> Option<Vec<u8>> {
+        let format = read_u16(bytes, self.fmt)?;
+        let channels = read_u16(bytes, self.fmt + 2)?;
+        let bits = read_u16(bytes, self.fmt + 14)?;
+        if format != 1 || channels != 1 || bits != 16 {
+            return None;
+        }
+        let sample_rate = read_u32(bytes, self.fmt + 4)?;
+        let samples = bytes.get(self.data..self.data + self.data_len as usize)?;
+        let (left, right) = ((1.0 - pan).min(1.0), (1.0 + pan).min(1.0));
+        let data_len = self.data_len * 2;
+
+        let mut out = Vec::with_capacity(44 + data_len as usize);
+        out.extend_from_slice(b"RIFF");
+        out.extend_from_slice(&(36 + data_len).to_le_bytes());
+        out.extend_from_slice(b"WAVEfmt ");
+        out.extend_from_slice(&16u32.to_le_bytes());
+        out.extend_from_slice(&1u16.to_le_bytes());
+        out.extend_from_slice(&2u16.to_le_bytes());
+        out.extend_from_slice(&sample_rate.to_le_bytes());
+        out.extend_from_slice(&(sample_rate * 4).to_le_bytes());
+        out.extend_from_slice(&4u16.to_le_bytes());
+        out.extend_from_slice(&16u16.to_le_bytes());
+        out.extend_from_slice(b"data");
+        out.extend_from_slice(&data_len.to_le_bytes());
+        for sample in samples.chunks_exact(2) {
+            let sample = i16::from_le_bytes([sample[0], sample[1]]) as f32;
+            out.extend_from_slice(&((sample * left) as i16).to_le_bytes());
+            out.extend_from_slice(&((sample * right) as i16).to_le_bytes());
+        }
+        Some(out)
+    }
+}
+
+fn wav_info(bytes: &[u8]) -> Option<WavInfo> {
+    if bytes.get(0..4)? != b"RIFF" || bytes.get(8..12)? != b"WAVE" {
+        return None;
+    }
+    let mut pos = 12;
+    let (mut fmt, mut data) = (None, None);
+    while let Some(id) = bytes.get(pos..pos + 4) {
+        let len = read_u32(bytes, pos + 4)?;
+        match id {
+            b"fmt " => fmt = Some(pos + 8),
+            b"data" => data = Some((pos + 8, len)),
+            _ => {}
+        }
+        // chunks are padded to an even length
+        pos += 8 + len as usize + (len as usize & 1);
+    }
+    let (fmt, (data, data_len)) = (fmt?, data?);
+    Some(WavInfo {
+        fmt,
+        byte_rate: read_u32(bytes, fmt + 8)?,
+        data,
+        data_len,
+    })
+}
+
+/// Reads the length of an Ogg Vorbis file from its last page.
+fn ogg_duration_sec(bytes: &[u8]) -> Option<f32> {
+    let find = |pattern: &[u8]| bytes.windows(pattern.len()).position(|w| w == pattern);
+    // the sample rate follows the version and channel count of the identification header
+    let rate = read_u32(bytes, find(b"\x01vorbis")? + 12)?;
+    let last_page = bytes.windows(4).rposition(|w| w == b"OggS")?;
+    let samples = u64::from_le_bytes(bytes.get(last_page + 6..last_page + 14)?.try_into().ok()?);
+    Some(samples as f32 / rate.max(1) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a 16 bit PCM WAV file with an odd sized chunk before `data`.
+    fn wav(channels: u16, sample_rate: u32, samples: &[i16]) -> Vec<u8> {
+        let data: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let mut out = Vec::default();
+        out.extend_from_slice(b"RIFF");
+        out.extend_from_slice(&(4 + 24 + 10 + 8 + data.len() as u32).to_le_bytes());
+        out.extend_from_slice(b"WAVEfmt ");
+        out.extend_from_slice(&16u32.to_le_bytes());
+        out.extend_from_slice(&1u16.to_le_bytes());
+        out.extend_from_slice(&channels.to_le_bytes());
+        out.extend_from_slice(&sample_rate.to_le_bytes());
+        out.extend_from_slice(&(sample_rate * channels as u32 * 2).to_le_bytes());
+        out.extend_from_slice(&(channels * 2).to_le_bytes());
+        out.extend_from_slice(&16u16.to_le_bytes());
+        out.extend_from_slice(b"LIST");
+        out.extend_from_slice(&1u32.to_le_bytes());
+        out.extend_from_slice(&[0, 0]);
+        out.extend_from_slice(b"data");
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&data);
+        out
+    }
+
+    #[test]
+    fn parse_options() {
+        let def =
+            SfxDef::parse("coin, a.wav, b.WAV, pitch=0.9..1.1, max=3, cooldown=0.05, volume=0.5")
+                .unwrap();
+        assert_eq!(def.key, "coin");
+        assert_eq!(def.paths, ["a.wav", "b.WAV"]);
+        assert_eq!(def.pitch, (0.9, 1.1));
+        assert_eq!(def.max_instances, 3);
+        assert_eq!(def.cooldown_sec, 0.05);
+        assert_eq!(def.volume, 0.5);
+        assert_eq!(
+            SfxDef::parse("jump, a.wav, pitch=1.2").unwrap().pitch,
+            (1.2, 1.2)
+        );
+        assert!(
+            SfxDef::parse("jump, a.wav, positional=true")
+                .unwrap()
+                .positional
+        );
+        assert!(!SfxDef::parse("jump, a.wav").unwrap().positional);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(SfxDef::parse(", a.wav").is_err());
+        assert!(SfxDef::parse("coin").is_err());
+        assert!(SfxDef::parse("coin, a.wav, loud=1").is_err());
+        assert!(SfxDef::parse("coin, a.wav, pitch=1.1..0.9").is_err());
+        assert!(SfxDef::parse("coin, a.wav, pitch=0").is_err());
+        assert!(SfxDef::parse("coin, a.wav, max=lots").is_err());
+        let err = SfxDef::parse("lost, a.wav, lost.ogg, pitch=0.9..1.1").unwrap_err();
+        assert!(err.contains("lost.ogg"));
+        assert!(SfxDef::parse("lost, lost.ogg, volume=0.5").is_ok());
+        assert!(SfxDef::parse("lost, lost.ogg, positional=true").is_err());
+        assert!(SfxDef::parse("coin, a.wav, positional=maybe").is_err());
+    }
+
+    #[test]
+    fn files_skips_bad_lines() {
+        let text = "# comment\ncoin, a.wav, b.wav\nbad\n\nlost, c.ogg\n";
+        assert_eq!(Sfx::files(text), ["a.wav", "b.wav", "c.ogg"]);
+    }
+
+    #[test]
+    fn wav_info_finds_chunks() {
+        let bytes = wav(1, 8000, &[0; 4000]);
+        let info = wav_info(&bytes).unwrap();
+        assert_eq!(info.data_len, 8000);
+        assert_eq!(info.duration_sec(), 0.5);
+        assert_eq!(&bytes[info.data - 8..info.data - 4], b"data");
+
+        assert!(wav_info(b"OggS").is_none());
+        assert!(wav_info(&bytes[..30]).is_none());
+    }
+
+    #[test]
+    fn pitch_scales_rates() {
+        let bytes = wav(1, 8000, &[0; 8]);
+        let pitched = wav_info(&bytes).unwrap().with_pitch(&bytes, 1.5);
+        let info = wav_info(&pitched).unwrap();
+        assert_eq!(read_u32(&pitched, info.fmt + 4), Some(12000));
+        assert_eq!(info.byte_rate, 24000);
+    }
+
+    #[test]
+    fn pan_makes_stereo() {
+        let bytes = wav(1, 8000, &[1000, -1000]);
+        let panned = wav_info(&bytes).unwrap().with_pan(&bytes, 0.5).unwrap();
+        let info = wav_info(&panned).unwrap();
+        assert_eq!(read_u16(&panned, info.fmt + 2), Some(2));
+        let samples: Vec<i16> = panned[info.data..]
+            .chunks_exact(2)
+            .map(|s| i16::from_le_bytes([s[0], s[1]]))
+            .collect();
+        assert_eq!(samples, [500, 1000, -500, -1000]);
+
+        let stereo = wav(2, 8000, &[0; 4]);
+        assert!(wav_info(&stereo).unwrap().with_pan(&stereo, 0.0).is_none());
+    }
+}
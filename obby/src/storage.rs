@@ -0,0 +1,86 @@
+/// Persistent key/value storage for small text blobs such as best times.
+///
+/// Native builds write one file per key into the `save` directory, wasm
+/// builds use the browser's `localStorage` through `storage.js`.
+pub trait Storage {
+    fn load(&self, key: &str) -> Option<String>;
+    fn save(&mut self, key: &str, value: &str);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub struct FileStorage {
+    pub dir: std::path::PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Storage for FileStorage {
+    fn load(&self, key: &str) -> Option<String> {
+        std::fs::read_to_string(self.dir.join(key)).ok()
+    }
+
+    fn save(&mut self, key: &str, value: &str) {
+        let res = std::fs::create_dir_all(&self.dir)
+            .and_then(|_| std::fs::write(self.dir.join(key), value));
+        if let Err(err) = res {
+            println!("failed to save {key}: {err}");
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+unsafe extern "C" {
+    fn obby_storage_load(key: *const u8, key_len: usize, buf: *mut u8, buf_len: usize) -> i32;
+    fn obby_storage_save(key: *const u8, key_len: usize, value: *const u8, value_len: usize);
+}
+
+#[cfg(target_arch = "wasm32")]
+pub struct LocalStorage;
+
+#[cfg(target_arch = "wasm32")]
+impl Storage for LocalStorage {
+    fn load(&self, key: &str) -> Option<String> {
+        let key = format!("obby.{key}");
+        // first call only asks for the length of the value
+        let len = unsafe { obby_storage_load(key.as_ptr(), key.len(), std::ptr::null_mut(), 0) };
+        if len < 0 {
+            return None;
+        }
+        let mut buf = vec![0; len as usize];
+        unsafe { obby_storage_load(key.as_ptr(), key.len(), buf.as_mut_ptr(), buf.len()) };
+        String::from_utf8(buf).ok()
+    }
+
+    fn save(&mut self, key: &str, value: &str) {
+        let key = format!("obby.{key}");
+        unsafe { obby_storage_save(key.as_ptr(), key.len(), value.as_ptr(), value.len()) };
+    }
+}
+
+pub fn default_storage() -> Box<dyn Storage> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        Box::new(LocalStorage)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        Box::new(FileStorage { dir: "save".into() })
+    }
+}
+
+/// Storage kept in memory, for tests.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryStorage {
+    pub values: std::collections::HashMap<String, String>,
+}
+
+#[cfg(test)]
+impl Storage for MemoryStorage {
+    fn load(&self, key: &str) -> Option<String> {
+        self.values.get(key).cloned()
+    }
+
+    fn save(&mut self, key: &str, value: &str) {
+        self.values.insert(key.to_string(), value.to_string());
+    }
+}
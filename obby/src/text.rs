@@ -0,0 +1,279 @@
+use macroquad::prelude::*;
+
+use crate::{loader::Loader, render::Align};
+
+/// Pixel sizes glyphs are rasterized and cached at, text is scaled down from
+/// the next size up so resizing the window does not fill the glyph atlas.
+const FONT_SIZES: [u16; 7] = [16, 24, 32, 48, 64, 96, 128];
+
+/// Distance between the baselines of two lines relative to the font size.
+pub const LINE_SPACING: f32 = 1.5;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TextStyle {
+    pub font_size: f32,
+    pub color: Color,
+    pub align: Align,
+    pub outline: Option<Color>,
+    pub shadow: Option<Color>,
+}
+
+impl TextStyle {
+    pub fn new(font_size: f32, color: Color) -> Self {
+        Self {
+            font_size,
+            color,
+            align: Align::Left,
+            outline: None,
+            shadow: None,
+        }
+    }
+
+    pub fn with_align(mut self, align: Align) -> Self {
+        self.align = align;
+        self
+    }
+
+    pub fn with_outline(mut self, color: Color) -> Self {
+        self.outline = Some(color);
+        self
+    }
+
+    pub fn with_shadow(mut self, color: Color) -> Self {
+        self.shadow = Some(color);
+        self
+    }
+}
+
+/// Measures, wraps and draws text with the bundled TTF font.
+///
+/// Characters the font does not have are drawn with macroquad's default font
+/// instead, so they never show up as holes and the rest of the line keeps its font.
+pub struct Text {
+    font: Option<Font>,
+    /// The same font parsed by fontdue, to look up which characters it has glyphs for.
+    glyphs: Option<fontdue::Font>,
+}
+
+impl Text {
+    /// Loads the font at `path` fetched by `loader`, falling back to the default font if it fails.
+    pub fn load(path: &str, loader: &Loader) -> Self {
+        let bytes = match loader.bytes(path) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                println!("failed to load font {err}");
+                return Self::default_font();
+            }
+        };
+        let mut font = match load_ttf_font_from_bytes(bytes) {
+            Ok(font) => font,
+            Err(err) => {
+                println!("failed to parse font '{path}': {err}");
+                return Self::default_font();
+            }
+        };
+        font.set_filter(FilterMode::Linear);
+        let text = Self {
+            font: Some(font),
+            glyphs: fontdue::Font::from_bytes(bytes, fontdue::FontSettings::default()).ok(),
+        };
+        let chars: Vec<char> = Font::latin_character_list()
+            .into_iter()
+            .filter(|c| text.covers(*c))
+            .collect();
+        if let Some(font) = &text.font {
+            for size in FONT_SIZES {
+                font.populate_font_cache(&chars, size);
+            }
+        }
+        text
+    }
+
+    pub fn default_font() -> Self {
+        Self {
+            font: None,
+            glyphs: None,
+        }
+    }
+
+    fn covers(&self, c: char) -> bool {
+        self.glyphs.as_ref().is_some_and(|g| g.has_glyph(c))
+    }
+
+    /// Splits `s` into runs of characters drawn with the same font.
+    fn runs<'a>(&self, s: &'a str) -> Vec<(&'a str, Option<&Font>)> {
+        split_runs(s, |c| self.covers(c))
+            .into_iter()
+            .map(|(run, covered)| (run, self.font.as_ref().filter(|_| covered)))
+            .collect()
+    }
+
+    /// Measures a single line of text.
+    pub fn measure(&self, s: &str, font_size: f32) -> TextDimensions {
+        let (size, scale) = glyph_size(font_size);
+        let mut dimensions = TextDimensions {
+            width: 0.0,
+            height: 0.0,
+            offset_y: 0.0,
+        };
+        for (run, font) in self.runs(s) {
+            let run = measure_text(run, font, size, scale);
+            dimensions.width += run.width;
+            dimensions.height = dimensions.height.max(run.height);
+            dimensions.offset_y = dimensions.offset_y.max(run.offset_y);
+        }
+        dimensions
+    }
+
+    /// Splits `s` into lines no wider than `max_width`, breaking at spaces.
+    ///
+    /// Explicit newlines are kept and a word wider than `max_width` gets a line of its own.
+    pub fn wrap(&self, s: &str, font_size: f32, max_width: f32) -> Vec<String> {
+        let mut lines = Vec::default();
+        for paragraph in s.split('\n') {
+            let mut line = String::new();
+            for word in paragraph.split(' ').filter(|w| !w.is_empty()) {
+                let candidate = if line.is_empty() {
+                    word.to_string()
+                } else {
+                    format!("{line} {word}")
+                };
+                if !line.is_empty() && self.measure(&candidate, font_size).width > max_width {
+                    lines.push(std::mem::replace(&mut line, word.to_string()));
+                } else {
+                    line = candidate;
+                }
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    /// Draws `s` with its first baseline at `y`, `x` is where it is aligned.
+    ///
+    /// Lines are split at newlines and placed [`LINE_SPACING`] apart.
+    pub fn draw(&self, s: &str, x: f32, y: f32, style: TextStyle) {
+        for (i, line) in s.split('\n').enumerate() {
+            let y = y + style.font_size * LINE_SPACING * i as f32;
+            self.draw_line(line, x, y, style);
+        }
+    }
+
+    /// Draws `s` wrapped to `max_width` and returns the height of the lines below `y`.
+    pub fn draw_wrapped(&self, s: &str, x: f32, y: f32, max_width: f32, style: TextStyle) -> f32 {
+        let lines = self.wrap(s, style.font_size, max_width);
+        for (i, line) in lines.iter().enumerate() {
+            let y = y + style.font_size * LINE_SPACING * i as f32;
+            self.draw_line(line, x, y, style);
+        }
+        style.font_size * LINE_SPACING * lines.len().saturating_sub(1) as f32
+    }
+
+    fn draw_line(&self, s: &str, x: f32, y: f32, style: TextStyle) {
+        let runs = self.runs(s);
+        let (size, scale) = glyph_size(style.font_size);
+        let x = match style.align {
+            Align::Left => x,
+            Align::Center => x - self.measure(s, style.font_size).width / 2.0,
+            Align::Right => x - self.measure(s, style.font_size).width,
+        };
+        let draw = |mut x: f32, y: f32, color: Color| {
+            for (run, font) in runs.iter() {
+                let params = TextParams {
+                    font: *font,
+                    font_size: size,
+                    font_scale: scale,
+                    color,
+                    ..Default::default()
+                };
+                draw_text_ex(run, x.floor(), y.floor(), params);
+                x += measure_text(run, *font, size, scale).width;
+            }
+        };
+        if let Some(color) = style.shadow {
+            let d = (style.font_size / 16.0).max(1.0);
+            draw(x + d, y + d, color);
+        }
+        if let Some(color) = style.outline {
+            let d = (style.font_size / 32.0).max(1.0);
+            for (dx, dy) in [
+                (-1.0, -1.0),
+                (0.0, -1.0),
+                (1.0, -1.0),
+                (-1.0, 0.0),
+                (1.0, 0.0),
+                (-1.0, 1.0),
+                (0.0, 1.0),
+                (1.0, 1.0),
+            ] {
+                draw(x + dx * d, y + dy * d, color);
+            }
+        }
+        draw(x, y, style.color);
+    }
+}
+
+/// Returns the cached glyph size to rasterize at and the scale that gives `font_size`.
+fn glyph_size(font_size: f32) -> (u16, f32) {
+    let size = FONT_SIZES
+        .into_iter()
+        .find(|s| *s as f32 >= font_size)
+        .unwrap_or(FONT_SIZES[FONT_SIZES.len() - 1]);
+    (size, font_size / size as f32)
+}
+
+/// Splits `s` where `covers` changes, returning each run and whether it is covered.
+fn split_runs(s: &str, covers: impl Fn(char) -> bool) -> Vec<(&str, bool)> {
+    let mut runs = Vec::default();
+    let mut start = 0;
+    let mut covered = None;
+    for (i, c) in s.char_indices() {
+        let c_covered = covers(c);
+        if let Some(covered) = covered
+            && covered != c_covered
+        {
+            runs.push((&s[start..i], covered));
+            start = i;
+        }
+        covered = Some(c_covered);
+    }
+    if let Some(covered) = covered {
+        runs.push((&s[start..], covered));
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_split_at_missing_glyphs() {
+        let covers = |c: char| c.is_ascii();
+        assert_eq!(split_runs("", covers), []);
+        assert_eq!(split_runs("SCORE", covers), [("SCORE", true)]);
+        assert_eq!(
+            split_runs("日 A日本B", covers),
+            [("日", false), (" A", true), ("日本", false), ("B", true)]
+        );
+    }
+
+    #[test]
+    fn bundled_font_glyphs() {
+        let bytes = include_bytes!("../../res/fonts/system.ttf");
+        let font = fontdue::Font::from_bytes(&bytes[..], fontdue::FontSettings::default()).unwrap();
+        assert!(font.has_glyph('A') && font.has_glyph('0') && font.has_glyph(' '));
+        assert!(!font.has_glyph('日'));
+        let danish = include_str!("../../res/locales/da.txt");
+        assert!(
+            danish
+                .chars()
+                .filter(|c| !c.is_control())
+                .all(|c| font.has_glyph(c))
+        );
+        for c in "ÆØÅæøå".chars() {
+            let (metrics, bitmap) = font.rasterize(c, 16.0);
+            assert!(metrics.width > 0 && bitmap.iter().any(|v| *v > 0), "{c}");
+        }
+    }
+}
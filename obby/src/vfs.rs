@@ -0,0 +1,203 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    future::Future,
+    io::{Cursor, Read},
+    pin::Pin,
+};
+
+use macroquad::file::load_file;
+use zip::ZipArchive;
+
+/// Archive read instead of loose files when it is found in the working directory.
+#[cfg(not(target_arch = "wasm32"))]
+const PACK_PATH: &str = "assets.zip";
+
+pub type FetchFuture = Pin<Box<dyn Future<Output = Result<Vec<u8>, String>> + Send>>;
+
+/// A source of asset files, paths are relative to the game root such as `res/maps.txt`.
+///
+/// Fetching returns a future so backends that have to go over the network,
+/// like loose files on the web, do not block the frame.
+pub trait Vfs {
+    /// Starts reading `path`, `None` if the backend knows it does not have the file.
+    fn fetch(&self, path: &str) -> Option<FetchFuture>;
+}
+
+/// Loose files below `root`, read with macroquad so it works on the web too.
+#[derive(Default)]
+pub struct DirectoryVfs {
+    pub root: String,
+}
+
+impl Vfs for DirectoryVfs {
+    fn fetch(&self, path: &str) -> Option<FetchFuture> {
+        let path = match self.root.is_empty() {
+            true => path.to_string(),
+            false => format!("{}/{path}", self.root),
+        };
+        Some(Box::pin(async move {
+            load_file(&path).await.map_err(|err| err.to_string())
+        }))
+    }
+}
+
+/// The `res` directory compiled into the executable by the `embed` feature.
+#[cfg(feature = "embed")]
+pub struct EmbeddedVfs {
+    files: HashMap<&'static str, &'static [u8]>,
+}
+
+#[cfg(feature = "embed")]
+impl Default for EmbeddedVfs {
+    fn default() -> Self {
+        // generated by build.rs as `&[("res/maps.txt", include_bytes!(...)), ...]`
+        let files: &[(&str, &[u8])] = include!(concat!(env!("OUT_DIR"), "/embedded.rs"));
+        Self {
+            files: files.iter().copied().collect(),
+        }
+    }
+}
+
+#[cfg(feature = "embed")]
+impl Vfs for EmbeddedVfs {
+    fn fetch(&self, path: &str) -> Option<FetchFuture> {
+        let bytes = self.files.get(path)?.to_vec();
+        Some(Box::pin(std::future::ready(Ok(bytes))))
+    }
+}
+
+/// Files stored or deflated in a zip archive, e.g. made with `zip -r assets.zip res`.
+///
+/// Only the central directory is read up front, files are inflated as they are fetched.
+pub struct ZipVfs {
+    archive: RefCell<ZipArchive<Cursor<Vec<u8>>>>,
+    /// Index in the archive of each file, with `/` as separator.
+    entries: HashMap<String, usize>,
+}
+
+impl ZipVfs {
+    pub fn parse(archive: Vec<u8>) -> Result<Self, String> {
+        let archive = ZipArchive::new(Cursor::new(archive)).map_err(|err| err.to_string())?;
+        let entries = archive
+            .file_names()
+            .filter(|name| !name.ends_with('/'))
+            .filter_map(|name| Some((name.replace('\\', "/"), archive.index_for_name(name)?)))
+            .collect();
+        Ok(Self {
+            archive: RefCell::new(archive),
+            entries,
+        })
+    }
+
+    fn read(&self, index: usize) -> Result<Vec<u8>, String> {
+        let mut archive = self.archive.borrow_mut();
+        let mut file = archive.by_index(index).map_err(|err| err.to_string())?;
+        let mut bytes = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut bytes)
+            .map_err(|err| err.to_string())?;
+        Ok(bytes)
+    }
+}
+
+impl Vfs for ZipVfs {
+    fn fetch(&self, path: &str) -> Option<FetchFuture> {
+        let index = *self.entries.get(path)?;
+        Some(Box::pin(std::future::ready(self.read(index))))
+    }
+}
+
+/// Backends tried in order, the first that has a file serves it.
+pub struct OverlayVfs {
+    pub layers: Vec<Box<dyn Vfs>>,
+}
+
+impl Vfs for OverlayVfs {
+    fn fetch(&self, path: &str) -> Option<FetchFuture> {
+        self.layers.iter().find_map(|layer| layer.fetch(path))
+    }
+}
+
+/// Embedded files with the `embed` feature, then `assets.zip` if there is one
+/// and loose files last.
+pub fn default_vfs() -> Box<dyn Vfs> {
+    let mut layers: Vec<Box<dyn Vfs>> = Vec::default();
+    #[cfg(feature = "embed")]
+    layers.push(Box::new(EmbeddedVfs::default()));
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Ok(archive) = std::fs::read(PACK_PATH) {
+        match ZipVfs::parse(archive) {
+            Ok(zip) => layers.push(Box::new(zip)),
+            Err(err) => println!("{PACK_PATH}: {err}"),
+        }
+    }
+    layers.push(Box::new(DirectoryVfs::default()));
+    Box::new(OverlayVfs { layers })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        task::{Context, Poll, Waker},
+    };
+
+    use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+    use super::*;
+
+    const MAPS: &[u8] = b"res/maps/level1.tmx\nres/maps/level2.tmx\n";
+
+    fn archive() -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::default()));
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        zip.add_directory("res/", stored).unwrap();
+        zip.start_file("res/maps.txt", stored).unwrap();
+        zip.write_all(MAPS).unwrap();
+        zip.start_file("res\\sfx.csv", deflated).unwrap();
+        zip.write_all(&MAPS.repeat(20)).unwrap();
+        zip.finish().unwrap().into_inner()
+    }
+
+    /// Fetches `path`, the futures of a zip archive are ready right away.
+    fn fetch(vfs: &dyn Vfs, path: &str) -> Option<Result<Vec<u8>, String>> {
+        let mut future = vfs.fetch(path)?;
+        match future
+            .as_mut()
+            .poll(&mut Context::from_waker(Waker::noop()))
+        {
+            Poll::Ready(res) => Some(res),
+            Poll::Pending => panic!("{path} is not ready"),
+        }
+    }
+
+    #[test]
+    fn zip_stored_entry() {
+        let zip = ZipVfs::parse(archive()).unwrap();
+        assert_eq!(fetch(&zip, "res/maps.txt"), Some(Ok(MAPS.to_vec())));
+    }
+
+    #[test]
+    fn zip_deflated_entry() {
+        let bytes = archive();
+        assert!(bytes.len() < MAPS.len() * 20, "the entry is not compressed");
+        let zip = ZipVfs::parse(bytes).unwrap();
+        assert_eq!(fetch(&zip, "res/sfx.csv"), Some(Ok(MAPS.repeat(20))));
+    }
+
+    #[test]
+    fn zip_missing_file() {
+        let zip = ZipVfs::parse(archive()).unwrap();
+        assert!(fetch(&zip, "res/missing.txt").is_none());
+        assert!(fetch(&zip, "res/").is_none());
+    }
+
+    #[test]
+    fn zip_truncated_archive() {
+        let bytes = archive();
+        assert!(ZipVfs::parse(bytes[..bytes.len() - 10].to_vec()).is_err());
+        assert!(ZipVfs::parse(bytes[..bytes.len() / 2].to_vec()).is_err());
+        assert!(ZipVfs::parse(Vec::default()).is_err());
+    }
+}
@@ -0,0 +1,436 @@
+use game::{Game, Mode};
+use macroquad::prelude::*;
+
+use crate::{
+    SKIN_INDEX,
+    animation::Animations,
+    assets::ENTITY_ATLAS,
+    centered,
+    locale::Locales,
+    particles::Particles,
+    records::{self, Split},
+    render::{Align, DrawList, Layer, Shape},
+    text::TextStyle,
+};
+
+/// Approximate height of capital letters relative to the font size.
+const CAP_HEIGHT: f32 = 0.7;
+
+/// Most rows of tiles in view, taller maps scroll vertically.
+const VIEW_ROWS: u32 = 16;
+
+/// What the game view is built from besides the game itself.
+///
+/// It holds plain data only, so a [`DrawList`] can be built and checked
+/// without a window or GPU.
+pub struct View<'a> {
+    /// Tile animations of each atlas, in the order of [`crate::assets::Assets::atlases`].
+    pub animations: Vec<&'a Animations>,
+    pub locales: &'a Locales,
+    pub particles: &'a Particles,
+    /// Time trial result shown under the center text.
+    pub split: Option<&'a Split>,
+    pub map_count: usize,
+}
+
+/// Builds the draw commands of the game view, without drawing anything.
+pub fn build_game(
+    game: &Game,
+    camera_offset_px: &mut Vec2,
+    target_width: f32,
+    target_height: f32,
+    view: &View,
+) -> DrawList {
+    let mut list = DrawList::default();
+    let background_color = game
+        .map_current
+        .as_ref()
+        .map(|x| x.background())
+        .unwrap_or_default();
+
+    let bounds = game.bounds;
+    let cell_size_px = target_height / bounds.height().clamp(1, VIEW_ROWS) as f32;
+    let min_px = Vec2::new(bounds.min.0 as f32, bounds.min.1 as f32) * cell_size_px;
+    let max_px = Vec2::new(bounds.max.0 as f32, bounds.max.1 as f32) * cell_size_px;
+    let view_px = Vec2::new(target_width, target_height);
+
+    // follow the midpoint between the players
+    *camera_offset_px = game.players_midpoint() * cell_size_px - view_px / 2.0;
+
+    if target_width < max_px.x - min_px.x {
+        camera_offset_px.x = camera_offset_px.x.clamp(min_px.x, max_px.x - target_width);
+    }
+    let max_y = (max_px.y - target_height).max(min_px.y);
+    camera_offset_px.y = camera_offset_px.y.clamp(min_px.y, max_y);
+
+    list.clear = Some(Color::from_rgba(
+        background_color.0,
+        background_color.1,
+        background_color.2,
+        255,
+    ));
+
+    build_grid(
+        &mut list,
+        game,
+        &view.animations,
+        *camera_offset_px,
+        cell_size_px,
+        view_px,
+    );
+
+    // Draw entities
+    for e in game.entities.values() {
+        let x = (e.pos.x - 0.5) * cell_size_px - camera_offset_px.x;
+        let y = (e.pos.y - 0.5) * cell_size_px - camera_offset_px.y;
+        let index = match e.variant {
+            game::EntityVariant::Unknown => 1.0,
+            game::EntityVariant::Player { skin, .. } => SKIN_INDEX[skin as usize],
+            game::EntityVariant::Goal => 2.0,
+            game::EntityVariant::Coin => 21.0,
+            game::EntityVariant::Cloud => 81.0,
+            game::EntityVariant::Ghost { skin } => SKIN_INDEX[skin as usize % SKIN_INDEX.len()],
+        };
+        let animations = view.animations[ENTITY_ATLAS];
+        let tile = animations.state_tile(index as u32, e.anim);
+        let index = animations.frame(tile, e.anim_elapsed_sec);
+        let color = match e.variant {
+            game::EntityVariant::Ghost { .. } => Color::new(1.0, 1.0, 1.0, 0.4),
+            _ => WHITE,
+        };
+        list.push(
+            Layer::Entities,
+            Shape::Sprite {
+                atlas: ENTITY_ATLAS,
+                index,
+                pos: Vec2::new(x, y),
+                size: Vec2::new(cell_size_px, cell_size_px),
+                color,
+                flip_x: matches!(e.dir_x, game::DirX::Left),
+                flip_y: false,
+                rotation: 0.0,
+            },
+        );
+    }
+
+    build_particles(&mut list, view.particles, *camera_offset_px, cell_size_px);
+    build_hud(&mut list, game, target_width, target_height, view);
+    list
+}
+
+fn build_hud(list: &mut DrawList, game: &Game, target_width: f32, target_height: f32, view: &View) {
+    let font_size = target_height / 8.0;
+    let margin = 16.0;
+    let transparent_color = Color::from_rgba(0, 0, 0, 255 / 4 * 2);
+    let locales = view.locales;
+    if let Some(message) = &game.center_text {
+        let height = font_size * CAP_HEIGHT;
+        let y = target_height / 2.0 - height;
+        list.rect(
+            Layer::Hud,
+            Rect::new(0.0, y - margin, target_width, height + margin * 2.0),
+            transparent_color,
+        );
+        list.text(
+            Layer::Hud,
+            locales.message(message),
+            Vec2::new(target_width / 2.0, y),
+            centered(font_size, WHITE).with_outline(BLACK),
+        );
+
+        if let Some(split) = view.split {
+            build_split(
+                list,
+                split,
+                locales,
+                target_width,
+                y + height + margin * 2.0,
+                target_height,
+            );
+        }
+    }
+
+    let font_size = target_height / 16.0;
+
+    // draw top bar
+    list.rect(
+        Layer::Hud,
+        Rect::new(0.0, 0.0, target_width, font_size),
+        transparent_color,
+    );
+
+    let mut top_bar = |text: String, x: f32, align: Align| {
+        let style = TextStyle::new(font_size, WHITE).with_align(align);
+        list.text(Layer::Hud, text, Vec2::new(x, margin), style);
+    };
+
+    let s = locales.format("hud_score", &[game.score.to_string()]);
+    top_bar(s, margin, Align::Left);
+
+    // draw LIVES, TIME or WINS
+    let s = match game.mode {
+        Mode::TimeTrial => {
+            let time = records::format_time(game.level_elapsed_sec);
+            locales.format("hud_time", &[time])
+        }
+        Mode::Versus => {
+            let wins = |player: usize| game.wins.get(player).copied().unwrap_or_default();
+            locales.format("hud_wins", &[wins(0).to_string(), wins(1).to_string()])
+        }
+        Mode::Story | Mode::Coop => locales.format("hud_lives", &[game.lives_extra.to_string()]),
+    };
+    top_bar(s, target_width / 3.0, Align::Center);
+
+    let s = locales.format("hud_coins", &[game.coins.to_string()]);
+    top_bar(s, target_width / 2.0, Align::Left);
+
+    let level = (game.level_current + 1).to_string();
+    let s = locales.format("hud_level", &[level, view.map_count.to_string()]);
+    top_bar(s, target_width - margin, Align::Right);
+}
+
+fn build_split(
+    list: &mut DrawList,
+    split: &Split,
+    locales: &Locales,
+    target_width: f32,
+    y: f32,
+    target_height: f32,
+) {
+    let font_size = target_height / 16.0;
+    let time = records::format_time(split.time_sec);
+    let mut lines = vec![(locales.format("split_time", &[time]), WHITE)];
+    if let Some(best) = split.best_sec {
+        let best = records::format_time(best);
+        lines.push((locales.format("split_best", &[best]), WHITE));
+    }
+    if let Some(delta) = split.delta_sec() {
+        let sign = if delta < 0.0 { "-" } else { "+" };
+        let color = if delta < 0.0 { GREEN } else { RED };
+        lines.push((
+            format!("{sign}{}", records::format_time(delta.abs())),
+            color,
+        ));
+    }
+    if split.is_record() {
+        lines.push((locales.get("new_record").to_string(), YELLOW));
+    }
+
+    let line_height = font_size * CAP_HEIGHT * 1.5;
+    for (i, (s, color)) in lines.into_iter().enumerate() {
+        let y = y + line_height * i as f32 + line_height - font_size * CAP_HEIGHT;
+        let pos = Vec2::new(target_width / 2.0, y);
+        list.text(Layer::Hud, s, pos, centered(font_size, color));
+    }
+}
+
+fn build_particles(
+    list: &mut DrawList,
+    particles: &Particles,
+    camera_offset_px: Vec2,
+    cell_size_px: f32,
+) {
+    for sprite in particles.sprites() {
+        let size = sprite.size * cell_size_px;
+        let x = sprite.pos.x * cell_size_px - camera_offset_px.x - size / 2.0;
+        let y = sprite.pos.y * cell_size_px - camera_offset_px.y - size / 2.0;
+        let shape = match sprite.frame {
+            Some(index) => Shape::Sprite {
+                atlas: ENTITY_ATLAS,
+                index,
+                pos: Vec2::new(x, y),
+                size: Vec2::new(size, size),
+                color: sprite.color,
+                flip_x: false,
+                flip_y: false,
+                rotation: 0.0,
+            },
+            None => Shape::Rect {
+                rect: Rect::new(x, y, size, size),
+                color: sprite.color,
+            },
+        };
+        list.push(Layer::Particles, shape);
+    }
+}
+
+/// Adds the visible tiles, foreground tiles go on their own layer above entities.
+fn build_grid(
+    list: &mut DrawList,
+    game: &Game,
+    animations: &[&Animations],
+    camera_offset_px: Vec2,
+    cell_size_px: f32,
+    view_px: Vec2,
+) {
+    let start = (camera_offset_px / cell_size_px).floor();
+    let end = ((camera_offset_px + view_px) / cell_size_px).floor();
+    for y in start.y as i32..=end.y as i32 {
+        for x in start.x as i32..=end.x as i32 {
+            let x_px = x as f32 * cell_size_px - camera_offset_px.x;
+            let y_px = y as f32 * cell_size_px - camera_offset_px.y;
+            if let Some(cell) = game.grid.get((x, y)) {
+                let layer = if cell.is_foreground {
+                    Layer::Foreground
+                } else {
+                    Layer::Tiles
+                };
+                let Some(animations) = animations.get(cell.tileset as usize) else {
+                    continue;
+                };
+                // tiles animate in step with each other, by time since the game started
+                let index = animations.frame(cell.variant, game.elapsed_total_sec);
+                let (flip_x, flip_y, rotation) = tile_transform(cell);
+                list.push(
+                    layer,
+                    Shape::Sprite {
+                        atlas: cell.tileset as usize,
+                        index,
+                        pos: Vec2::new(x_px, y_px),
+                        size: Vec2::new(cell_size_px, cell_size_px),
+                        color: WHITE,
+                        flip_x,
+                        flip_y,
+                        rotation,
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// Turns the flips of a tile, as Tiled stores them, into the texture flips and
+/// clockwise rotation macroquad applies in that order.
+///
+/// A diagonal flip is a vertical flip followed by a quarter turn, and flipping
+/// after the turn is the same as flipping along the other axis before it.
+fn tile_transform(tile: &game::Tile) -> (bool, bool, f32) {
+    if tile.flip_d {
+        (tile.flip_y, !tile.flip_x, std::f32::consts::FRAC_PI_2)
+    } else {
+        (tile.flip_x, tile.flip_y, 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use game::{AsciiMap, Map, Message, spawn};
+    use macroquad::rand::RandGenerator;
+
+    use super::*;
+    use crate::locale::Locale;
+
+    const MAP: &str = "background = #102030
+---
+......
+.P..~.
+######
+";
+
+    /// A game on `MAP` with its player spawned and tiles streamed in.
+    fn game() -> Game {
+        let map = AsciiMap::parse("test.map", MAP).unwrap();
+        let mut game = Game {
+            bounds: map.bounds(),
+            map_current: Some(Rc::new(map)),
+            ..Default::default()
+        };
+        spawn::spawn_player(&mut game, Vec2::new(1.5, 1.5), 0);
+        game.stream_chunks();
+        game
+    }
+
+    fn build(game: &Game, split: Option<&Split>) -> DrawList {
+        let animations = Animations::default();
+        let locales = Locales::new(vec![Locale::parse(
+            "en.txt",
+            "en",
+            include_str!("../../res/locales/en.txt"),
+        )]);
+        let particles = Particles::new(Vec::default(), RandGenerator::new());
+        let view = View {
+            animations: vec![&animations],
+            locales: &locales,
+            particles: &particles,
+            split,
+            map_count: 3,
+        };
+        // three rows of 50 pixels fill the height
+        let mut camera_offset_px = Vec2::ZERO;
+        build_game(game, &mut camera_offset_px, 300.0, 150.0, &view)
+    }
+
+    fn sprites(list: &DrawList, layer: Layer) -> Vec<(u32, Vec2)> {
+        list.commands
+            .iter()
+            .filter(|c| c.layer == layer)
+            .filter_map(|c| match c.shape {
+                Shape::Sprite { index, pos, .. } => Some((index, pos)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn texts(list: &DrawList) -> Vec<&str> {
+        list.commands
+            .iter()
+            .filter_map(|c| match &c.shape {
+                Shape::Text { text, .. } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn game_view() {
+        let list = build(&game(), None);
+        assert_eq!(list.clear, Some(Color::from_rgba(0x10, 0x20, 0x30, 255)));
+
+        // the camera centers the player, whose cell starts at 50 pixels, the last block is out of view
+        let camera_x = 50.0 + 25.0 - 150.0;
+        let blocks: Vec<(u32, Vec2)> = (0..5)
+            .map(|x| (20, Vec2::new(x as f32 * 50.0 - camera_x, 100.0)))
+            .collect();
+        // empty cells are drawn as the blank tile 0
+        let tiles = sprites(&list, Layer::Tiles);
+        let tiles: Vec<(u32, Vec2)> = tiles.into_iter().filter(|(i, _)| *i != 0).collect();
+        assert_eq!(tiles, blocks);
+        // entity tiles are not drawn as tiles, the player is the only entity spawned
+        assert_eq!(
+            sprites(&list, Layer::Entities),
+            [(SKIN_INDEX[0] as u32, Vec2::new(50.0 - camera_x, 50.0))]
+        );
+        assert_eq!(
+            texts(&list),
+            ["SCORE: 0", "LIVES: 0", "COINS: 0", "LEVEL 1 OF 3"]
+        );
+
+        let layers: Vec<Layer> = list.sorted().iter().map(|c| c.layer).collect();
+        assert!(layers.is_sorted());
+        assert_eq!(layers.last(), Some(&Layer::Hud));
+    }
+
+    #[test]
+    fn center_text_with_split() {
+        let mut game = game();
+        game.center_text = Some(Message::new("you_won"));
+        let split = Split {
+            time_sec: 61.5,
+            best_sec: Some(62.0),
+        };
+        let list = build(&game, Some(&split));
+        let texts = texts(&list);
+        assert_eq!(
+            texts[..5],
+            [
+                "YOU WON!",
+                "TIME 1:01.50",
+                "BEST 1:02.00",
+                "-0:00.50",
+                "NEW RECORD!"
+            ]
+        );
+    }
+}
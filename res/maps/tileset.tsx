@@ -4,11 +4,130 @@
  <tile id="1" type="entity player"/>
  <tile id="2" type="entity goal"/>
  <tile id="20" type="block"/>
- <tile id="21" type="entity coin"/>
+ <tile id="21" type="entity coin">
+  <animation>
+   <frame tileid="21" duration="300"/>
+   <frame tileid="22" duration="100"/>
+   <frame tileid="23" duration="100"/>
+   <frame tileid="22" duration="100"/>
+  </animation>
+ </tile>
  <tile id="40" type="block"/>
- <tile id="41" type="block deadly"/>
+ <tile id="41" type="block deadly">
+  <animation>
+   <frame tileid="41" duration="250"/>
+   <frame tileid="43" duration="250"/>
+   <frame tileid="44" duration="250"/>
+   <frame tileid="43" duration="250"/>
+  </animation>
+ </tile>
  <tile id="60" type="block"/>
  <tile id="80" type="foreground"/>
  <tile id="81" type="entity cloud"/>
  <tile id="100" type="foreground"/>
+ <tile id="120">
+  <properties>
+   <property name="run" type="int" value="140"/>
+   <property name="jump" type="int" value="160"/>
+   <property name="fall" type="int" value="180"/>
+   <property name="dead" type="int" value="200"/>
+   <property name="won" type="int" value="220"/>
+  </properties>
+ </tile>
+ <tile id="121">
+  <properties>
+   <property name="run" type="int" value="141"/>
+   <property name="jump" type="int" value="161"/>
+   <property name="fall" type="int" value="181"/>
+   <property name="dead" type="int" value="201"/>
+   <property name="won" type="int" value="221"/>
+  </properties>
+ </tile>
+ <tile id="122">
+  <properties>
+   <property name="run" type="int" value="142"/>
+   <property name="jump" type="int" value="162"/>
+   <property name="fall" type="int" value="182"/>
+   <property name="dead" type="int" value="202"/>
+   <property name="won" type="int" value="222"/>
+  </properties>
+ </tile>
+ <tile id="123">
+  <properties>
+   <property name="run" type="int" value="143"/>
+   <property name="jump" type="int" value="163"/>
+   <property name="fall" type="int" value="183"/>
+   <property name="dead" type="int" value="203"/>
+   <property name="won" type="int" value="223"/>
+  </properties>
+ </tile>
+ <tile id="124">
+  <properties>
+   <property name="run" type="int" value="144"/>
+   <property name="jump" type="int" value="164"/>
+   <property name="fall" type="int" value="184"/>
+   <property name="dead" type="int" value="204"/>
+   <property name="won" type="int" value="224"/>
+  </properties>
+ </tile>
+ <tile id="140">
+  <animation>
+   <frame tileid="120" duration="120"/>
+   <frame tileid="140" duration="120"/>
+  </animation>
+ </tile>
+ <tile id="141">
+  <animation>
+   <frame tileid="121" duration="120"/>
+   <frame tileid="141" duration="120"/>
+  </animation>
+ </tile>
+ <tile id="142">
+  <animation>
+   <frame tileid="122" duration="120"/>
+   <frame tileid="142" duration="120"/>
+  </animation>
+ </tile>
+ <tile id="143">
+  <animation>
+   <frame tileid="123" duration="120"/>
+   <frame tileid="143" duration="120"/>
+  </animation>
+ </tile>
+ <tile id="144">
+  <animation>
+   <frame tileid="124" duration="120"/>
+   <frame tileid="144" duration="120"/>
+  </animation>
+ </tile>
+ <tile id="220">
+  <animation>
+   <frame tileid="120" duration="150"/>
+   <frame tileid="220" duration="150"/>
+  </animation>
+ </tile>
+ <tile id="221">
+  <animation>
+   <frame tileid="121" duration="150"/>
+   <frame tileid="221" duration="150"/>
+  </animation>
+ </tile>
+ <tile id="222">
+  <animation>
+   <frame tileid="122" duration="150"/>
+   <frame tileid="222" duration="150"/>
+  </animation>
+ </tile>
+ <tile id="223">
+  <animation>
+   <frame tileid="123" duration="150"/>
+   <frame tileid="223" duration="150"/>
+  </animation>
+ </tile>
+ <tile id="224">
+  <animation>
+   <frame tileid="124" duration="150"/>
+   <frame tileid="224" duration="150"/>
+  </animation>
+ </tile>
 </tileset>
@@ -0,0 +1,24 @@
+// localStorage bindings used by obby/src/storage.rs on wasm
+miniquad_add_plugin({
+    register_plugin: function (importObject) {
+        importObject.env.obby_storage_load = function (key, key_len, buf, buf_len) {
+            var k = new TextDecoder().decode(new Uint8Array(wasm_memory.buffer, key, key_len));
+            var value = window.localStorage.getItem(k);
+            if (value === null) {
+                return -1;
+            }
+            var bytes = new TextEncoder().encode(value);
+            if (bytes.length <= buf_len) {
+                new Uint8Array(wasm_memory.buffer, buf, buf_len).set(bytes);
+            }
+            return bytes.length;
+        };
+        importObject.env.obby_storage_save = function (key, key_len, value, value_len) {
+            var k = new TextDecoder().decode(new Uint8Array(wasm_memory.buffer, key, key_len));
+            var v = new TextDecoder().decode(new Uint8Array(wasm_memory.buffer, value, value_len));
+            window.localStorage.setItem(k, v);
+        };
+    },
+    version: 1,
+    name: "obby_storage"
+});